@@ -1,22 +1,79 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, ItemFn, ItemStruct};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse_macro_input, Ident, ItemFn, ItemStruct, LitInt, LitStr, Path};
 
 extern crate proc_macro;
 
-#[proc_macro_derive(Event)]
+/// Implements `Event` for a `Serialize` and `Deserialize` struct and registers it
+/// in the global event registry, so stored and network events decode by name.
+/// Structs missing either serde derive are rejected with an error pointing at them.
+///
+/// The event name defaults to the struct name and can be pinned with
+/// `#[event(name = "...")]`, so renaming the struct does not break stored events.
+/// `#[event(id = ...)]` gives the event a stable numeric wire id.
+#[proc_macro_derive(Event, attributes(event))]
 pub fn event(item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as ItemStruct);
     let name = &input.ident;
 
+    if !input.generics.params.is_empty() {
+        return syn::Error::new_spanned(&input.generics, "events can not be generic")
+            .to_compile_error()
+            .into();
+    }
+
+    let mut event_name = name.to_string();
+    let mut wire_id = quote! { None };
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("event"))
+    {
+        let result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                event_name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else if meta.path.is_ident("id") {
+                let id = meta.value()?.parse::<LitInt>()?.base10_parse::<u32>()?;
+                wire_id = quote! { Some(#id) };
+                Ok(())
+            } else {
+                Err(meta.error("unsupported event attribute, expected `name` or `id`"))
+            }
+        });
+
+        if let Err(err) = result {
+            return err.to_compile_error().into();
+        }
+    }
+
+    // Checked up front so a missing serde derive is reported on the struct itself.
+    let serde_check = quote_spanned! {name.span()=>
+        const _: fn() = || {
+            fn assert_serde<T: ::ptwar::event::SerdeEvent>() {}
+            assert_serde::<#name>();
+        };
+    };
+
     quote! {
-        impl crate::event::Event for #name {
+        #serde_check
+
+        impl ::ptwar::event::Event for #name {
             fn get_name_static() -> &'static str {
-                stringify!(#name)
+                #event_name
             }
 
             fn get_name(&self) -> &'static str {
-                stringify!(#name)
+                #event_name
+            }
+
+            fn get_wire_id_static() -> Option<u32> {
+                #wire_id
+            }
+
+            fn get_wire_id(&self) -> Option<u32> {
+                #wire_id
             }
 
             fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
@@ -26,15 +83,86 @@ pub fn event(item: TokenStream) -> TokenStream {
             fn as_any(&self) -> &dyn std::any::Any {
                 self
             }
+
+            fn encode(&self) -> ::ptwar::event::EncodeResult {
+                ::ptwar::event::EncodedEvent::encode(self)
+            }
+        }
+
+        ::ptwar::inventory::submit! {
+            ::ptwar::event::EventRegistration::new(
+                #event_name,
+                #wire_id,
+                ::ptwar::event::decode_event::<#name>,
+            )
         }
     }
     .into()
 }
 
+/// Turns an `async fn(tick, server)` into a `TickHandler` struct of the same name.
+///
+/// Accepts `order` (an `SOrder` variant, `First` by default), `every` (run every N ticks,
+/// `1` by default) and `name` (the function name by default), e.g.
+/// `#[tick_system(order = Second, every = 10, name = "resource_production")]`.
+///
+/// The rest of the `SystemSchedule` can be declared too, every one of them may be repeated:
+/// `run_if` (an `async fn(tick, server) -> bool`), `before` and `after` (other system names),
+/// `reads` and `writes` (a `SystemData` variant), e.g.
+/// `#[tick_system(after = "movement", reads = World, writes = Stats, run_if = at_war)]`.
+///
+/// The generated `schedule` function returns the declared `SystemSchedule`
+/// and `register` adds the system to a `GameLoop` with it.
 #[proc_macro_attribute]
-pub fn tick_system(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn tick_system(attr: TokenStream, item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as ItemFn);
     let name = &input.sig.ident;
+    let vis = &input.vis;
+
+    let mut system_name = name.to_string();
+    let mut order = format_ident!("First");
+    let mut every = quote! { 1 };
+    let mut schedule = Vec::new();
+
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("order") {
+            order = meta.value()?.parse::<Ident>()?;
+            Ok(())
+        } else if meta.path.is_ident("every") {
+            let value = meta.value()?.parse::<LitInt>()?;
+
+            if value.base10_parse::<u128>()? == 0 {
+                return Err(syn::Error::new_spanned(value, "`every` must be at least 1"));
+            }
+
+            every = quote! { #value };
+            Ok(())
+        } else if meta.path.is_ident("name") {
+            system_name = meta.value()?.parse::<LitStr>()?.value();
+            Ok(())
+        } else if meta.path.is_ident("run_if") {
+            let condition = meta.value()?.parse::<Path>()?;
+            schedule.push(quote! { .run_if(#condition) });
+            Ok(())
+        } else if meta.path.is_ident("before") || meta.path.is_ident("after") {
+            let method = meta.path.get_ident().cloned();
+            let system = meta.value()?.parse::<LitStr>()?;
+            schedule.push(quote! { .#method(#system) });
+            Ok(())
+        } else if meta.path.is_ident("reads") || meta.path.is_ident("writes") {
+            let method = meta.path.get_ident().cloned();
+            let data = meta.value()?.parse::<Ident>()?;
+            schedule.push(quote! { .#method(::ptwar::schedule::SystemData::#data) });
+            Ok(())
+        } else {
+            Err(meta.error(
+                "unsupported tick_system argument, expected `order`, `every`, `name`, \
+                 `run_if`, `before`, `after`, `reads` or `writes`",
+            ))
+        }
+    });
+
+    parse_macro_input!(attr with parser);
 
     let first_arg = match input.sig.inputs.first().unwrap() {
         syn::FnArg::Typed(pat) => match pat.pat.as_ref() {
@@ -55,13 +183,38 @@ pub fn tick_system(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let block = input.block;
 
     quote! {
-        pub struct #name{}
+        #[allow(non_camel_case_types)]
+        #vis struct #name;
 
-        #[async_trait]
+        impl #name {
+            pub const NAME: &'static str = #system_name;
+            pub const ORDER: ::ptwar::system::SOrder = ::ptwar::system::SOrder::#order;
+            pub const EVERY: ::ptwar::system::Tick = #every;
+
+            /// Cadence, run conditions, ordering and data access declared on the system.
+            pub fn schedule() -> ::ptwar::system::SystemSchedule {
+                ::ptwar::system::SystemSchedule::every(Self::EVERY) #(#schedule)*
+            }
+
+            /// Adds the system to `game_loop` with its declared order and schedule.
+            pub fn register(game_loop: &mut ::ptwar::system::GameLoop) {
+                game_loop.add_system_with(Self::ORDER, Self::schedule(), #name);
+            }
+        }
+
+        #[::ptwar::async_trait::async_trait]
         impl ::ptwar::worker::TickHandler for #name {
-            async fn handle(&self, #first_arg: Tick, #second_arg: Arc<PtWarServer>) {
+            async fn handle(
+                &self,
+                #first_arg: ::ptwar::system::Tick,
+                #second_arg: ::std::sync::Arc<::ptwar::system::PtWarServer>,
+            ) {
                 #block
             }
+
+            fn name(&self) -> &'static str {
+                Self::NAME
+            }
         }
     }
     .into()
@@ -0,0 +1,261 @@
+use clap::Parser;
+use hexx::Hex;
+use ptwar::core::SaveInterval;
+use ptwar::system::{Tick, TPS};
+use ptwar::world::WorldShape;
+use ptwar::PTWarConfig;
+use serde::Deserialize;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Config file read when `--config` is not given, it is fine for it not to exist.
+pub const DEFAULT_CONFIG_PATH: &str = "ptwar.toml";
+
+/// PTWar game server. Flags override the config file, which overrides the defaults.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// TOML config file.
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Seed of a new world, ignored when resuming from a save.
+    #[arg(long)]
+    pub seed: Option<u32>,
+
+    /// Radius in regions of a new hexagonal world, 0 is a single region.
+    #[arg(long)]
+    pub world_size: Option<u32>,
+
+    /// Size in regions of a new rectangular world, like `8x4`.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_rect, conflicts_with = "world_size")]
+    pub world_rect: Option<(u32, u32)>,
+
+    /// Radius in tiles of the regions of a new world.
+    #[arg(long)]
+    pub region_radius: Option<u32>,
+
+    /// Generates the regions of a new world on first access instead of on start.
+    #[arg(long)]
+    pub lazy_regions: bool,
+
+    /// Unloads the unmodified regions not accessed within this many ticks.
+    #[arg(long, value_name = "TICKS")]
+    pub evict_regions_every: Option<Tick>,
+
+    /// Ticks per second.
+    #[arg(long)]
+    pub tps: Option<TPS>,
+
+    /// Worker tasks running systems and event handlers, defaults to the number of CPUs.
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// Jobs that can wait for a worker before dispatching blocks.
+    #[arg(long)]
+    pub job_buffer: Option<usize>,
+
+    /// Directory of the snapshots and event log, resumed from on start.
+    #[arg(long)]
+    pub save_dir: Option<PathBuf>,
+
+    /// `none`, a number of ticks like `600t`, or a duration like `90s`, `5m` or `1h`.
+    #[arg(long)]
+    pub save_interval: Option<SaveInterval>,
+
+    /// Address of the Prometheus `/metrics` endpoint.
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Disables the `/metrics` endpoint.
+    #[arg(long, conflicts_with = "metrics_addr")]
+    pub no_metrics: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    world: WorldSection,
+    game: GameSection,
+    save: SaveSection,
+    metrics: MetricsSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WorldSection {
+    seed: Option<u32>,
+    size: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    /// Axial `[x, y]` coordinates of the regions of a custom shape.
+    regions: Option<Vec<[i32; 2]>>,
+    region_radius: Option<u32>,
+    lazy: Option<bool>,
+    evict_every: Option<Tick>,
+}
+
+impl WorldSection {
+    fn shape(&self) -> Result<Option<WorldShape>, Box<dyn Error>> {
+        let rect = match (self.width, self.height) {
+            (Some(width), Some(height)) => Some(WorldShape::Rectangle { width, height }),
+            (None, None) => None,
+            _ => return Err("world width and height must be set together".into()),
+        };
+        let size = self.size.map(WorldShape::Hexagon);
+        let custom = self.regions.as_ref().map(|regions| {
+            WorldShape::Custom(regions.iter().map(|[x, y]| Hex::new(*x, *y)).collect())
+        });
+
+        let mut shapes = [size, rect, custom].into_iter().flatten();
+        let shape = shapes.next();
+
+        if shapes.next().is_some() {
+            return Err("only one of world size, width/height or regions can be set".into());
+        }
+
+        Ok(shape)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GameSection {
+    tps: Option<TPS>,
+    workers: Option<usize>,
+    job_buffer: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SaveSection {
+    dir: Option<PathBuf>,
+    interval: Option<String>,
+    keep: Option<usize>,
+    compact_every: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MetricsSection {
+    enabled: Option<bool>,
+    addr: Option<SocketAddr>,
+}
+
+impl Cli {
+    /// Builds the game config from the defaults, the config file and then the flags.
+    pub fn load(&self) -> Result<PTWarConfig, Box<dyn Error>> {
+        let file = self.read_file()?;
+        let mut config = PTWarConfig::default();
+
+        let world = &mut config.world;
+        world.seed = self.seed.or(file.world.seed).unwrap_or(world.seed);
+
+        let shape = match (self.world_size, self.world_rect) {
+            (Some(size), _) => Some(WorldShape::Hexagon(size)),
+            (_, Some((width, height))) => Some(WorldShape::Rectangle { width, height }),
+            _ => file.world.shape()?,
+        };
+
+        if let Some(shape) = shape {
+            world.shape = shape;
+        }
+
+        if world.shape.regions().is_empty() {
+            return Err("the world must have at least one region".into());
+        }
+
+        world.region_radius = self
+            .region_radius
+            .or(file.world.region_radius)
+            .unwrap_or(world.region_radius);
+        world.lazy = self.lazy_regions || file.world.lazy.unwrap_or(world.lazy);
+
+        config.evict_regions_every = self
+            .evict_regions_every
+            .or(file.world.evict_every)
+            .or(config.evict_regions_every);
+
+        if config.evict_regions_every == Some(0) {
+            return Err("evict_every must be at least 1".into());
+        }
+
+        config.tps = self.tps.or(file.game.tps).unwrap_or(config.tps);
+        config.workers = self.workers.or(file.game.workers).unwrap_or(config.workers);
+        config.job_buffer = self
+            .job_buffer
+            .or(file.game.job_buffer)
+            .unwrap_or(config.job_buffer);
+
+        if config.tps == 0 {
+            return Err("tps must be at least 1".into());
+        }
+
+        if config.workers == 0 || config.job_buffer == 0 {
+            return Err("workers and job_buffer must be at least 1".into());
+        }
+
+        let save = &mut config.save;
+
+        if let Some(dir) = self.save_dir.clone().or(file.save.dir) {
+            save.dir = dir;
+        }
+
+        if let Some(interval) = self.save_interval {
+            save.interval = interval;
+        } else if let Some(interval) = file.save.interval {
+            save.interval = interval.parse()?;
+        }
+
+        save.keep = file.save.keep.unwrap_or(save.keep);
+        save.compact_every = file.save.compact_every.unwrap_or(save.compact_every);
+
+        let metrics_addr = self
+            .metrics_addr
+            .or(file.metrics.addr)
+            .or(config.metrics_addr);
+
+        config.metrics_addr = if self.no_metrics || file.metrics.enabled == Some(false) {
+            None
+        } else {
+            metrics_addr
+        };
+
+        Ok(config)
+    }
+
+    fn read_file(&self) -> Result<FileConfig, Box<dyn Error>> {
+        let path = match &self.config {
+            Some(path) => path.clone(),
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+
+                if !path.exists() {
+                    return Ok(FileConfig::default());
+                }
+
+                path
+            }
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+        toml::from_str(&content)
+            .map_err(|err| format!("invalid config {}: {}", path.display(), err).into())
+    }
+}
+
+fn parse_rect(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got `{}`", value))?;
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|err| format!("invalid size `{}`: {}", n, err))
+    };
+
+    Ok((parse(width)?, parse(height)?))
+}
@@ -1,10 +1,34 @@
+mod config;
+
+use crate::config::Cli;
+use clap::Parser;
+use log::{error, info};
 use ptwar::PTWar;
 
 #[tokio::main]
 async fn main() {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    let mut gloop = PTWar::new();
+    let config = match Cli::parse().load() {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
+    let mut gloop = match PTWar::load_or_new(config) {
+        Ok(gloop) => gloop,
+        Err(err) => {
+            error!("Failed to load the saved game: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(err) = gloop.start().await {
+        error!("Failed to start the game loop: {}", err);
+        std::process::exit(2);
+    }
 
-    gloop.start().await;
+    info!("Server stopped");
 }
@@ -0,0 +1,57 @@
+//! Overhead of dispatching a phase of jobs to the workers and waiting for all of them,
+//! which the game loop pays for every system stage and event depth of every tick.
+
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ptwar::system::{PtWarServer, Tick};
+use ptwar::worker::{PWorkerManager, TickHandler, WorkerJob};
+use ptwar::world::PtWorld;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+struct Noop;
+
+#[async_trait]
+impl TickHandler for Noop {
+    async fn handle(&self, _tick: Tick, _server: Arc<PtWarServer>) {}
+}
+
+fn empty_world() -> PtWorld {
+    PtWorld::new(0, 1, HashSet::new())
+}
+
+fn worker_phase(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = Arc::new(PtWarServer::from_world(empty_world(), 0));
+    let handler: Arc<Box<dyn TickHandler>> = Arc::new(Box::new(Noop));
+
+    let mut group = c.benchmark_group("worker_phase");
+
+    for jobs in [1, 16, 256] {
+        let mut manager = runtime.block_on(async { PWorkerManager::new(server.clone(), 4, 512) });
+
+        group.bench_with_input(BenchmarkId::from_parameter(jobs), &jobs, |b, &jobs| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let started = Instant::now();
+
+                    for _ in 0..iters {
+                        for _ in 0..jobs {
+                            manager.send(WorkerJob::Tick(handler.clone())).await;
+                        }
+
+                        manager.wait_all().await;
+                    }
+
+                    started.elapsed()
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, worker_phase);
+criterion_main!(benches);
@@ -1,6 +1,15 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::hash::Hash;
 use std::ops::Deref;
 
+/// Types with a fixed set of `'static` values that a [`Static`] can point to.
+///
+/// Used to turn a deserialized value back into a reference to its static instance.
+pub trait StaticRegistry: PartialEq + Sized + 'static {
+    fn registry() -> &'static [Self];
+}
+
 #[derive(Debug)]
 pub struct Static<T: 'static>(pub &'static T);
 
@@ -20,6 +29,13 @@ impl<T> Clone for Static<T> {
     }
 }
 
+impl<T: StaticRegistry> Static<T> {
+    /// Position of the value in [`StaticRegistry::registry`], stable as long as the registry is.
+    pub fn index(&self) -> Option<usize> {
+        T::registry().iter().position(|v| std::ptr::eq(v, self.0))
+    }
+}
+
 impl<T> Eq for Static<T> {}
 
 impl<T> PartialEq for Static<T> {
@@ -39,3 +55,24 @@ impl<T> From<&'static T> for Static<T> {
         Static(value)
     }
 }
+
+impl<T: Serialize> Serialize for Static<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Static<T>
+where
+    T: StaticRegistry + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = T::deserialize(deserializer)?;
+
+        T::registry()
+            .iter()
+            .find(|v| **v == value)
+            .map(Static)
+            .ok_or_else(|| D::Error::custom("value is not present in the static registry"))
+    }
+}
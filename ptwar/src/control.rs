@@ -0,0 +1,67 @@
+use crate::system::TPS;
+use log::warn;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Slowest speed accepted by [`ControlCommand::SetSpeed`], lower ones are raised to it.
+pub const MIN_SPEED: f64 = 0.01;
+/// Fastest speed accepted by [`ControlCommand::SetSpeed`], higher ones are lowered to it.
+pub const MAX_SPEED: f64 = 1000.0;
+
+/// Commands accepted by a running [`GameLoop`](crate::system::GameLoop), applied between ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    /// Pauses the loop and runs the given number of ticks right away.
+    Step(u64),
+    SetTps(TPS),
+    /// Multiplies the tick rate, `2.0` runs twice as many ticks per second.
+    /// Clamped to [`MIN_SPEED`]..=[`MAX_SPEED`].
+    SetSpeed(f64),
+    /// Stops the loop once the current tick is over.
+    Shutdown,
+}
+
+/// Cloneable handle to control a [`GameLoop`](crate::system::GameLoop) from other tasks.
+///
+/// Commands sent before the loop starts are applied as soon as it does.
+#[derive(Clone)]
+pub struct GameLoopControl {
+    tx: UnboundedSender<ControlCommand>,
+}
+
+impl GameLoopControl {
+    pub(crate) fn new(tx: UnboundedSender<ControlCommand>) -> Self {
+        Self { tx }
+    }
+
+    pub fn pause(&self) {
+        self.send(ControlCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(ControlCommand::Resume);
+    }
+
+    pub fn step(&self, ticks: u64) {
+        self.send(ControlCommand::Step(ticks));
+    }
+
+    pub fn set_tps(&self, tps: TPS) {
+        self.send(ControlCommand::SetTps(tps));
+    }
+
+    pub fn set_speed(&self, speed: f64) {
+        self.send(ControlCommand::SetSpeed(speed));
+    }
+
+    pub fn shutdown(&self) {
+        self.send(ControlCommand::Shutdown);
+    }
+
+    pub fn send(&self, command: ControlCommand) {
+        if self.tx.send(command).is_err() {
+            warn!("Game loop stopped, dropping control command {:?}", command);
+        }
+    }
+}
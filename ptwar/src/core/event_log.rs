@@ -0,0 +1,178 @@
+use crate::event::EncodedEvent;
+use crate::system::Tick;
+use crate::world::snapshot::invalid_data;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const EVENT_LOG_PREFIX: &str = "events-";
+const EVENT_LOG_EXTENSION: &str = "log";
+
+/// An event dispatched by the game loop, `depth` is the cascade sub-phase it was handled in.
+#[derive(Serialize, Deserialize)]
+pub struct EventRecord {
+    pub tick: Tick,
+    pub depth: usize,
+    pub event: EncodedEvent,
+}
+
+/// Append-only log of every dispatched event, split in segments named after their first tick.
+///
+/// A new segment is started after every save, so a save plus the segments
+/// from its tick onward are enough to rebuild the state of any later tick.
+///
+/// The writer never removes segments, the ones left by another game are archived by
+/// [`SaveStore::claim`](crate::core::save::SaveStore::claim) before the game starts.
+pub struct EventLogWriter {
+    dir: PathBuf,
+    segment: Option<BufWriter<File>>,
+}
+
+impl EventLogWriter {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            segment: None,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn segment_path(dir: &Path, tick: Tick) -> PathBuf {
+        dir.join(format!(
+            "{}{}.{}",
+            EVENT_LOG_PREFIX, tick, EVENT_LOG_EXTENSION
+        ))
+    }
+
+    pub fn append(&mut self, record: &EventRecord) -> io::Result<()> {
+        let segment = match &mut self.segment {
+            Some(segment) => segment,
+            None => {
+                fs::create_dir_all(&self.dir)?;
+
+                let file = File::create(Self::segment_path(&self.dir, record.tick))?;
+
+                self.segment.insert(BufWriter::new(file))
+            }
+        };
+
+        bincode::serialize_into(segment, record).map_err(invalid_data)
+    }
+
+    /// Hands buffered records to the OS, called at the end of every tick.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.segment {
+            Some(segment) => segment.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Syncs the current segment to disk and starts a new one with the next record.
+    pub fn roll(&mut self) -> io::Result<()> {
+        if let Some(mut segment) = self.segment.take() {
+            segment.flush()?;
+            segment.get_ref().sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Log segments in `dir` as `(first tick, path)`, oldest first.
+pub fn segments(dir: impl AsRef<Path>) -> io::Result<Vec<(Tick, PathBuf)>> {
+    let dir = dir.as_ref();
+
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut segments = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path
+            .extension()
+            .is_some_and(|ext| ext == EVENT_LOG_EXTENSION)
+        {
+            let tick = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.strip_prefix(EVENT_LOG_PREFIX))
+                .and_then(|tick| tick.parse().ok());
+
+            if let Some(tick) = tick {
+                segments.push((tick, path));
+            }
+        }
+    }
+
+    segments.sort_by_key(|(tick, _)| *tick);
+
+    Ok(segments)
+}
+
+fn read_segment(path: &Path, records: &mut Vec<EventRecord>) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    loop {
+        match bincode::deserialize_from::<_, EventRecord>(&mut reader) {
+            Ok(record) => records.push(record),
+            Err(err) => {
+                let eof = matches!(
+                    &*err,
+                    bincode::ErrorKind::Io(io_err) if io_err.kind() == ErrorKind::UnexpectedEof
+                );
+
+                // A crash mid-append leaves a truncated last record, keep what was read.
+                if !eof {
+                    warn!("Stopped reading event log {}: {}", path.display(), err);
+                }
+
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads every logged event from `from_tick` onward, in dispatch order.
+pub fn read_records(dir: impl AsRef<Path>, from_tick: Tick) -> io::Result<Vec<EventRecord>> {
+    let mut records = Vec::new();
+
+    for (_, path) in segments(dir)? {
+        read_segment(&path, &mut records)?;
+    }
+
+    records.retain(|record| record.tick >= from_tick);
+
+    info!(
+        "Read {} logged events from tick {}",
+        records.len(),
+        from_tick
+    );
+
+    Ok(records)
+}
+
+/// Removes the segments that only hold events before `tick`.
+pub fn prune(dir: impl AsRef<Path>, tick: Tick) -> io::Result<()> {
+    let segments = segments(dir)?;
+
+    for window in segments.windows(2) {
+        let ((start, path), (next_start, _)) = (&window[0], &window[1]);
+
+        if *next_start <= tick {
+            info!("Removing event log segment of tick {}", start);
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use crate::metrics::TimingKey;
+use crate::system::PtWarServer;
+use crate::worker::{PWorkerStatus, WorkerProbe};
+use log::{debug, error, info};
+use std::fmt::Write as _;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::{ProcessesToUpdate, System};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+
+pub const DEFAULT_METRICS_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9464));
+
+/// Largest request head accepted, scrapers send a few hundred bytes at most.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const WORKER_STATUSES: [PWorkerStatus; 3] = [
+    PWorkerStatus::Idle,
+    PWorkerStatus::Working,
+    PWorkerStatus::Stopped,
+];
+
+/// Serves the server metrics in the Prometheus text exposition format on `GET /metrics`.
+pub struct MetricsExporter {
+    server: Arc<PtWarServer>,
+    workers: WorkerProbe,
+    sys: Mutex<System>,
+}
+
+impl MetricsExporter {
+    pub fn new(server: Arc<PtWarServer>, workers: WorkerProbe) -> Self {
+        Self {
+            server,
+            workers,
+            sys: Mutex::new(System::new()),
+        }
+    }
+
+    /// Accepts scrapes on `addr` until the task is dropped.
+    pub async fn serve(self, addr: SocketAddr) -> io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let exporter = Arc::new(self);
+
+        info!(
+            "Serving metrics on http://{}/metrics",
+            listener.local_addr()?
+        );
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    error!("Failed to accept metrics connection: {}", err);
+                    continue;
+                }
+            };
+
+            let exporter = exporter.clone();
+
+            tokio::spawn(async move {
+                match tokio::time::timeout(REQUEST_TIMEOUT, exporter.handle(stream)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => debug!("Metrics request from {} failed: {}", peer, err),
+                    Err(_) => debug!("Metrics request from {} timed out", peer),
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut request = Vec::with_capacity(1024);
+        let mut buf = [0u8; 1024];
+
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            if request.len() > MAX_REQUEST_SIZE {
+                return respond(&mut stream, "431 Request Header Fields Too Large", "").await;
+            }
+
+            let read = stream.read(&mut buf).await?;
+
+            if read == 0 {
+                return Ok(());
+            }
+
+            request.extend_from_slice(&buf[..read]);
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let mut line = request
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+
+        let method = line.next().unwrap_or_default();
+        let path = line
+            .next()
+            .and_then(|target| target.split('?').next())
+            .unwrap_or_default();
+
+        match (method, path) {
+            ("GET", "/metrics") => respond(&mut stream, "200 OK", &self.render().await).await,
+            ("GET", _) => respond(&mut stream, "404 Not Found", "not found\n").await,
+            _ => {
+                respond(
+                    &mut stream,
+                    "405 Method Not Allowed",
+                    "method not allowed\n",
+                )
+                .await
+            }
+        }
+    }
+
+    /// Current metrics in the text exposition format.
+    pub async fn render(&self) -> String {
+        let mut out = String::new();
+
+        {
+            let stats = self.server.stats.read().await;
+
+            gauge(&mut out, "ptwar_tick", "Current game tick.", stats.tick);
+            gauge(
+                &mut out,
+                "ptwar_tps",
+                "Ticks per second achieved over the last second.",
+                stats.tps,
+            );
+            gauge(
+                &mut out,
+                "ptwar_target_tps",
+                "Ticks per second the game loop aims for.",
+                stats.target_tps,
+            );
+            gauge(
+                &mut out,
+                "ptwar_ticks_behind",
+                "Ticks the game loop is late by.",
+                stats.ticks_behind,
+            );
+            counter(
+                &mut out,
+                "ptwar_missed_ticks_total",
+                "Ticks skipped or delayed because the loop fell behind.",
+                stats.missed_ticks,
+            );
+            gauge(
+                &mut out,
+                "ptwar_paused",
+                "Whether the game loop is paused.",
+                stats.paused as u8,
+            );
+
+            let durations = &stats.tick_durations;
+
+            header(
+                &mut out,
+                "ptwar_tick_duration_seconds",
+                "Time spent running a tick.",
+                "histogram",
+            );
+
+            for (bound, count) in durations.buckets() {
+                let _ = writeln!(
+                    out,
+                    "ptwar_tick_duration_seconds_bucket{{le=\"{}\"}} {}",
+                    bound, count
+                );
+            }
+
+            let _ = writeln!(
+                out,
+                "ptwar_tick_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+                durations.count()
+            );
+            let _ = writeln!(out, "ptwar_tick_duration_seconds_sum {}", durations.sum());
+            let _ = writeln!(
+                out,
+                "ptwar_tick_duration_seconds_count {}",
+                durations.count()
+            );
+
+            counter(
+                &mut out,
+                "ptwar_events_processed_total",
+                "Events dispatched to handlers since start.",
+                stats.events_processed,
+            );
+            gauge(
+                &mut out,
+                "ptwar_tick_events",
+                "Events dispatched during the last tick.",
+                stats.tick_events,
+            );
+
+            header(
+                &mut out,
+                "ptwar_job_duration_seconds",
+                "Run time of systems and event handlers over their recent runs.",
+                "summary",
+            );
+
+            for (key, timing) in stats.timings.slowest() {
+                let labels = job_labels(&key);
+
+                for quantile in [0.5, 0.99] {
+                    let _ = writeln!(
+                        out,
+                        "ptwar_job_duration_seconds{{{},quantile=\"{}\"}} {}",
+                        labels,
+                        quantile,
+                        timing.percentile(quantile).as_secs_f64()
+                    );
+                }
+
+                let _ = writeln!(
+                    out,
+                    "ptwar_job_duration_seconds_sum{{{}}} {}",
+                    labels,
+                    timing.total().as_secs_f64()
+                );
+                let _ = writeln!(
+                    out,
+                    "ptwar_job_duration_seconds_count{{{}}} {}",
+                    labels,
+                    timing.count()
+                );
+            }
+
+            header(
+                &mut out,
+                "ptwar_job_failures_total",
+                "Runs of systems and event handlers that panicked.",
+                "counter",
+            );
+
+            for (key, failures) in &stats.timings.failures {
+                let _ = writeln!(
+                    out,
+                    "ptwar_job_failures_total{{{}}} {}",
+                    job_labels(key),
+                    failures
+                );
+            }
+        }
+
+        gauge(
+            &mut out,
+            "ptwar_events_queued",
+            "Events waiting for the next event phase.",
+            self.server.events_queue.lock().await.len(),
+        );
+        gauge(
+            &mut out,
+            "ptwar_events_scheduled",
+            "Events scheduled for a later tick.",
+            self.server.scheduled_events.lock().await.len(),
+        );
+
+        gauge(
+            &mut out,
+            "ptwar_worker_messages_in_flight",
+            "Jobs sent to workers and not finished yet.",
+            self.workers.messages_in_flight(),
+        );
+
+        header(
+            &mut out,
+            "ptwar_worker_status",
+            "Current status of each worker.",
+            "gauge",
+        );
+
+        for (id, status) in self.workers.statuses().await.into_iter().enumerate() {
+            for candidate in WORKER_STATUSES {
+                let _ = writeln!(
+                    out,
+                    "ptwar_worker_status{{worker=\"{}\",status=\"{}\"}} {}",
+                    id,
+                    candidate.as_str(),
+                    (status == candidate) as u8
+                );
+            }
+        }
+
+        {
+            let world = self.server.world.read().await;
+            let tiles = world
+                .regions
+                .values()
+                .map(|region| region.tiles.len())
+                .sum::<usize>();
+
+            gauge(
+                &mut out,
+                "ptwar_world_regions",
+                "Regions loaded in the world.",
+                world.regions.len(),
+            );
+            gauge(
+                &mut out,
+                "ptwar_world_size_regions",
+                "Regions of the world, loaded or not.",
+                world.region_hexes.len(),
+            );
+            gauge(
+                &mut out,
+                "ptwar_world_regions_modified",
+                "Regions changed since they were generated.",
+                world.modified.len(),
+            );
+            gauge(
+                &mut out,
+                "ptwar_world_tiles",
+                "Tiles loaded in the world.",
+                tiles,
+            );
+        }
+
+        if let Some(memory) = self.memory().await {
+            gauge(
+                &mut out,
+                "ptwar_memory_bytes",
+                "Resident memory of the server process.",
+                memory,
+            );
+        }
+
+        out
+    }
+
+    async fn memory(&self) -> Option<u64> {
+        let pid = sysinfo::get_current_pid().ok()?;
+        let mut sys = self.sys.lock().await;
+
+        sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+
+        sys.process(pid).map(|process| process.memory())
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn job_labels(key: &TimingKey) -> String {
+    format!("kind=\"{}\",name=\"{}\"", key.kind(), escape(key.name()))
+}
+
+/// Escapes a label value, see the text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,35 +1,164 @@
+pub mod event_log;
+pub mod exporter;
+pub mod save;
+
 use crate::system::{PtWarServer, Tick};
 use crate::worker::TickHandler;
 use async_trait::async_trait;
+use log::info;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+pub const DEFAULT_SAVE_DIR: &str = "saves";
+pub const DEFAULT_SAVE_KEEP: usize = 3;
+pub const DEFAULT_SAVE_COMPACT_EVERY: usize = 10;
+pub const DEFAULT_SAVE_INTERVAL: SaveInterval = SaveInterval::Time(Duration::from_secs(5 * 60));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SaveInterval {
     None,
     Tick(Tick),
     Time(Duration),
 }
+
+impl SaveInterval {
+    /// Whether periodic saves are off, `Tick(0)` never comes due either.
+    pub fn is_disabled(&self) -> bool {
+        matches!(self, SaveInterval::None | SaveInterval::Tick(0))
+    }
+}
+
+/// Parses `none`, a number of ticks like `600t`, or a duration like `90s`, `5m` or `1h`.
+/// Amounts must be at least 1.
+impl FromStr for SaveInterval {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+
+        if value.eq_ignore_ascii_case("none") {
+            return Ok(SaveInterval::None);
+        }
+
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| format!("invalid save interval {:?}", value))?;
+
+        if amount == 0 {
+            return Err(format!(
+                "invalid save interval {:?}, use none to disable saves",
+                value
+            ));
+        }
+
+        let secs = |multiplier: u64| {
+            amount
+                .checked_mul(multiplier)
+                .map(|secs| SaveInterval::Time(Duration::from_secs(secs)))
+                .ok_or_else(|| format!("save interval {:?} is too long", value))
+        };
+
+        match unit.trim() {
+            "t" => Ok(SaveInterval::Tick(amount as Tick)),
+            "s" => secs(1),
+            "m" => secs(60),
+            "h" => secs(60 * 60),
+            _ => Err(format!(
+                "invalid save interval {:?}, expected none, <ticks>t or a duration in s, m or h",
+                value
+            )),
+        }
+    }
+}
+
+pub struct SaveConfig {
+    pub dir: PathBuf,
+    pub interval: SaveInterval,
+    /// Number of snapshots kept on disk, `0` keeps all of them.
+    pub keep: usize,
+    /// Number of delta saves between full snapshots, `0` always writes full snapshots.
+    pub compact_every: usize,
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_SAVE_DIR),
+            interval: DEFAULT_SAVE_INTERVAL,
+            keep: DEFAULT_SAVE_KEEP,
+            compact_every: DEFAULT_SAVE_COMPACT_EVERY,
+        }
+    }
+}
+
 pub struct SaveGameSystem {
     save_interval: SaveInterval,
 }
 
+impl SaveGameSystem {
+    pub fn new(save_interval: SaveInterval) -> Self {
+        Self { save_interval }
+    }
+}
+
 #[async_trait]
 impl TickHandler for SaveGameSystem {
     async fn handle(&self, tick: Tick, server: Arc<PtWarServer>) {
         let should_save = match self.save_interval {
-            SaveInterval::None => false,
+            SaveInterval::None | SaveInterval::Tick(0) => false,
             SaveInterval::Tick(interval) => tick % interval == 0,
             SaveInterval::Time(interval) => {
                 let stats = server.stats.read().await;
 
-                stats.last_save.map_or(true, |(last_tick, last_save)| {
-                    last_save.elapsed() >= interval
+                stats.last_save.is_none_or(|(_last_tick, last_save)| {
+                    server.now().duration_since(last_save) >= interval
                 })
             }
         };
 
         if should_save {
-            server.save().await;
+            server.request_save();
+        }
+    }
+}
+
+/// Logs the [`PtWorld::state_hash`](crate::world::PtWorld::state_hash) of the ticks it runs on,
+/// to compare servers or a run with its replay.
+pub struct StateHashSystem;
+
+#[async_trait]
+impl TickHandler for StateHashSystem {
+    async fn handle(&self, tick: Tick, server: Arc<PtWarServer>) {
+        let hash = server.world.read().await.state_hash();
+
+        info!("World state hash at tick {}: {:016x}", tick, hash);
+    }
+}
+
+/// Unloads the regions left idle since its previous run, see [`PtWorld::evict_idle`](crate::world::PtWorld::evict_idle).
+pub struct RegionEvictionSystem;
+
+#[async_trait]
+impl TickHandler for RegionEvictionSystem {
+    async fn handle(&self, tick: Tick, server: Arc<PtWarServer>) {
+        let mut world = server.world.write().await;
+        let evicted = world.evict_idle();
+
+        if evicted > 0 {
+            info!(
+                "Evicted {} idle regions at tick {}, {} of {} still loaded",
+                evicted,
+                tick,
+                world.regions.len(),
+                world.region_hexes.len()
+            );
         }
     }
 }
@@ -0,0 +1,445 @@
+use crate::core::event_log;
+use crate::event::EventScheduler;
+use crate::system::Tick;
+use crate::world::snapshot::invalid_data;
+use crate::world::PtWorld;
+use log::{debug, info, warn};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Starts every save file, so they are told apart from bare [`PtWorld::save`] snapshots.
+pub const SAVE_MAGIC: [u8; 4] = *b"PTWS";
+/// Bumped every time the layout around the world data of save files changes.
+pub const SAVE_VERSION: u32 = 1;
+
+const SNAPSHOT_PREFIX: &str = "world-";
+const DELTA_PREFIX: &str = "delta-";
+const SAVE_EXTENSION: &str = "bin";
+const ARCHIVE_PREFIX: &str = "archive-";
+const TMP_EXTENSION: &str = "tmp";
+
+/// Game state read back from a [`SaveStore`].
+pub struct SavedGame {
+    pub world: PtWorld,
+    pub scheduled_events: EventScheduler,
+    pub tick: Tick,
+}
+
+/// Directory of rotating world snapshots, one file per saved tick.
+///
+/// Every file holds a header, the world or a delta of it, then the pending scheduled events.
+///
+/// Most saves only write a delta with the regions and tiles changed since the
+/// previous save, every `compact_every` deltas a new full snapshot is written instead.
+///
+/// Files are first written to a temporary file and renamed into place,
+/// so a crash mid-save never leaves a truncated save behind.
+///
+/// A directory only ever holds the saves of a single timeline, see [`SaveStore::claim`].
+pub struct SaveStore {
+    dir: PathBuf,
+    keep: usize,
+    compact_every: usize,
+}
+
+impl SaveStore {
+    /// `keep` is the number of full snapshots to retain, `0` keeps all of them.
+    /// `compact_every` is the number of deltas written before a new full snapshot, `0` disables deltas.
+    pub fn new(dir: impl Into<PathBuf>, keep: usize, compact_every: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            keep,
+            compact_every,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn keep(&self) -> usize {
+        self.keep
+    }
+
+    pub fn compact_every(&self) -> usize {
+        self.compact_every
+    }
+
+    pub fn snapshot_path(&self, tick: Tick) -> PathBuf {
+        self.dir
+            .join(format!("{}{}.{}", SNAPSHOT_PREFIX, tick, SAVE_EXTENSION))
+    }
+
+    pub fn delta_path(&self, base_tick: Tick, tick: Tick) -> PathBuf {
+        self.dir.join(format!(
+            "{}{}-{}.{}",
+            DELTA_PREFIX, base_tick, tick, SAVE_EXTENSION
+        ))
+    }
+
+    fn save_stem<'a>(path: &'a Path, prefix: &str) -> Option<&'a str> {
+        if path.extension()? != SAVE_EXTENSION {
+            return None;
+        }
+
+        path.file_stem()?.to_str()?.strip_prefix(prefix)
+    }
+
+    fn list<T: Ord + Copy>(
+        &self,
+        parse: impl Fn(&Path) -> Option<T>,
+    ) -> io::Result<Vec<(T, PathBuf)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut saves = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+
+            if let Some(key) = parse(&path) {
+                saves.push((key, path));
+            }
+        }
+
+        saves.sort_by_key(|(key, _)| *key);
+
+        Ok(saves)
+    }
+
+    /// All full snapshots in the directory, oldest first.
+    pub fn snapshots(&self) -> io::Result<Vec<(Tick, PathBuf)>> {
+        self.list(|path| Self::save_stem(path, SNAPSHOT_PREFIX)?.parse().ok())
+    }
+
+    /// All deltas in the directory as `((base_tick, tick), path)`, oldest first.
+    pub fn deltas(&self) -> io::Result<Vec<((Tick, Tick), PathBuf)>> {
+        self.list(|path| {
+            let (base, tick) = Self::save_stem(path, DELTA_PREFIX)?.split_once('-')?;
+
+            Some((base.parse().ok()?, tick.parse().ok()?))
+        })
+    }
+
+    /// Deltas written on top of the snapshot of `base_tick`, oldest first.
+    pub fn deltas_of(&self, base_tick: Tick) -> io::Result<Vec<(Tick, PathBuf)>> {
+        Ok(self
+            .deltas()?
+            .into_iter()
+            .filter(|((base, _), _)| *base == base_tick)
+            .map(|((_, tick), path)| (tick, path))
+            .collect())
+    }
+
+    pub fn latest(&self) -> io::Result<Option<PathBuf>> {
+        Ok(self.snapshots()?.pop().map(|(_, path)| path))
+    }
+
+    /// Prepares the directory for a game resumed at `resume`, or for a new game when `None`.
+    ///
+    /// Saves and event logs of another game, or past `resume` after resuming from an older save,
+    /// would be rotated in place of the new ones, so they are moved to a new `archive-<secs>`
+    /// subdirectory, which is returned when anything was moved.
+    /// Otherwise only the event log segments logged after the resumed save are discarded,
+    /// they would be logged again.
+    pub fn claim(&self, resume: Option<Tick>) -> io::Result<Option<PathBuf>> {
+        let snapshots = self.snapshots()?;
+        let deltas = self.deltas()?;
+        let segments = event_log::segments(&self.dir)?;
+
+        let other_timeline = match resume {
+            Some(resume) => {
+                snapshots.iter().any(|(tick, _)| *tick > resume)
+                    || deltas.iter().any(|((_, tick), _)| *tick > resume)
+            }
+            None => !snapshots.is_empty() || !deltas.is_empty() || !segments.is_empty(),
+        };
+
+        if !other_timeline {
+            for (tick, path) in segments {
+                if resume.is_some_and(|resume| tick >= resume) {
+                    warn!("Discarding event log segment of tick {}", tick);
+                    fs::remove_file(path)?;
+                }
+            }
+
+            return Ok(None);
+        }
+
+        let archive = self.archive_dir()?;
+
+        warn!(
+            "{} holds saves of another timeline, moving them to {}",
+            self.dir.display(),
+            archive.display()
+        );
+
+        let paths = snapshots
+            .into_iter()
+            .map(|(_, path)| path)
+            .chain(deltas.into_iter().map(|(_, path)| path))
+            .chain(segments.into_iter().map(|(_, path)| path));
+
+        for path in paths {
+            if let Some(name) = path.file_name() {
+                fs::rename(&path, archive.join(name))?;
+            }
+        }
+
+        Ok(Some(archive))
+    }
+
+    fn archive_dir(&self) -> io::Result<PathBuf> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut archive = self.dir.join(format!("{}{}", ARCHIVE_PREFIX, secs));
+        let mut n = 1;
+
+        while archive.exists() {
+            archive = self.dir.join(format!("{}{}-{}", ARCHIVE_PREFIX, secs, n));
+            n += 1;
+        }
+
+        fs::create_dir_all(&archive)?;
+
+        Ok(archive)
+    }
+
+    /// Reads a single full snapshot file, ignoring any delta written on top of it.
+    pub fn load_snapshot(path: impl AsRef<Path>) -> io::Result<SavedGame> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        read_header(&mut reader)?;
+        let (world, tick) = PtWorld::read_snapshot(&mut reader)?;
+        let scheduled_events = read_scheduled_events(&mut reader)?;
+
+        Ok(SavedGame {
+            world,
+            scheduled_events,
+            tick,
+        })
+    }
+
+    /// Loads the latest full snapshot and replays its deltas on top of it.
+    pub fn load_latest(&self) -> io::Result<Option<SavedGame>> {
+        let Some((base_tick, path)) = self.snapshots()?.pop() else {
+            return Ok(None);
+        };
+
+        let mut save = Self::load_snapshot(path)?;
+
+        let deltas = self.deltas_of(base_tick)?;
+
+        for (_, path) in deltas.iter() {
+            let mut reader = BufReader::new(File::open(path)?);
+
+            read_header(&mut reader)?;
+            save.tick = save.world.read_delta(&mut reader)?;
+            save.scheduled_events = read_scheduled_events(&mut reader)?;
+        }
+
+        if !deltas.is_empty() {
+            info!(
+                "Replayed {} deltas on top of snapshot {}, resuming at tick {}",
+                deltas.len(),
+                base_tick,
+                save.tick
+            );
+        }
+
+        Ok(Some(save))
+    }
+
+    /// Saves the changes of `world` since its last save, compacting into a full snapshot when due.
+    ///
+    /// Returns `None` without writing anything when a delta would be empty.
+    pub fn write(
+        &self,
+        world: &mut PtWorld,
+        scheduled_events: &mut EventScheduler,
+        tick: Tick,
+    ) -> io::Result<Option<PathBuf>> {
+        let latest = self.snapshots()?.pop().map(|(tick, _)| tick);
+
+        let full = match (world.dirty.base, latest) {
+            _ if self.compact_every == 0 => true,
+            (Some(base), Some(latest)) if base == latest && base < tick => {
+                self.deltas_of(base)?.len() >= self.compact_every
+            }
+            _ => true,
+        };
+
+        if !full && world.dirty.is_empty() && !scheduled_events.is_changed() {
+            debug!(
+                "Nothing changed since the last save, skipping the delta of tick {}",
+                tick
+            );
+            return Ok(None);
+        }
+
+        let path = if full {
+            self.write_snapshot(world, scheduled_events, tick)?
+        } else {
+            self.write_delta(world, scheduled_events, tick)?
+        };
+
+        scheduled_events.mark_saved();
+
+        Ok(Some(path))
+    }
+
+    /// Atomically writes a full snapshot of `world` and drops the saves past `keep`.
+    pub fn write_snapshot(
+        &self,
+        world: &mut PtWorld,
+        scheduled_events: &EventScheduler,
+        tick: Tick,
+    ) -> io::Result<PathBuf> {
+        let path = self.snapshot_path(tick);
+
+        self.write_atomic(&path, |writer| {
+            write_header(&mut *writer)?;
+            world.write_snapshot(tick, &mut *writer)?;
+            bincode::serialize_into(writer, scheduled_events).map_err(invalid_data)
+        })?;
+
+        world.mark_saved(tick);
+
+        if let Err(err) = self.rotate(tick) {
+            warn!(
+                "failed to rotate snapshots in {}: {}",
+                self.dir.display(),
+                err
+            );
+        }
+
+        Ok(path)
+    }
+
+    fn write_delta(
+        &self,
+        world: &mut PtWorld,
+        scheduled_events: &EventScheduler,
+        tick: Tick,
+    ) -> io::Result<PathBuf> {
+        let base_tick = world.dirty.base.unwrap_or_default();
+        let path = self.delta_path(base_tick, tick);
+
+        self.write_atomic(&path, |writer| {
+            write_header(&mut *writer)?;
+            world.write_delta(tick, &mut *writer)?;
+            bincode::serialize_into(writer, scheduled_events).map_err(invalid_data)
+        })?;
+
+        world.mark_saved(base_tick);
+
+        Ok(path)
+    }
+
+    fn write_atomic(
+        &self,
+        path: &Path,
+        write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let tmp_path = path.with_extension(TMP_EXTENSION);
+
+        let result = File::create(&tmp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+
+            write(&mut writer)?;
+
+            writer.flush()?;
+            writer.get_ref().sync_all()
+        });
+
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+
+        fs::rename(&tmp_path, path)?;
+
+        // Persist the rename itself, not every platform allows opening a directory.
+        if let Ok(dir) = fs::File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        Ok(())
+    }
+
+    /// Drops the deltas of older snapshots and the snapshots past `keep`,
+    /// never the snapshot of `written` that was just saved.
+    fn rotate(&self, written: Tick) -> io::Result<()> {
+        // Deltas are only ever replayed on top of the latest snapshot.
+        for ((base, tick), path) in self.deltas()? {
+            if base != written {
+                info!("Removing compacted delta of tick {}", tick);
+                fs::remove_file(path)?;
+            }
+        }
+
+        let older = self
+            .snapshots()?
+            .into_iter()
+            .filter(|(tick, _)| *tick != written)
+            .collect::<Vec<_>>();
+
+        let removed = match self.keep {
+            0 => 0,
+            keep => (older.len() + 1).saturating_sub(keep),
+        };
+
+        for (tick, path) in &older[..removed] {
+            info!("Removing old snapshot of tick {}", tick);
+            fs::remove_file(path)?;
+        }
+
+        // Logged events are only replayed on top of a snapshot that is still around.
+        let oldest = older
+            .get(removed)
+            .map_or(written, |(tick, _)| (*tick).min(written));
+
+        event_log::prune(&self.dir, oldest)
+    }
+}
+
+fn write_header(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(&SAVE_MAGIC)?;
+    bincode::serialize_into(writer, &SAVE_VERSION).map_err(invalid_data)
+}
+
+fn read_header(reader: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0; SAVE_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+
+    if magic != SAVE_MAGIC {
+        return Err(invalid_data(
+            "not a save store file, read bare world snapshots with PtWorld::load",
+        ));
+    }
+
+    let version: u32 = bincode::deserialize_from(&mut *reader).map_err(invalid_data)?;
+
+    if version != SAVE_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported save file version {}, expected {}",
+            version, SAVE_VERSION
+        )));
+    }
+
+    Ok(())
+}
+
+fn read_scheduled_events(reader: &mut impl Read) -> io::Result<EventScheduler> {
+    bincode::deserialize_from(reader).map_err(invalid_data)
+}
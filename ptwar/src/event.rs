@@ -1,6 +1,10 @@
+use crate::system::{PtWarServer, Tick};
 use async_trait::async_trait;
+use log::{error, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 pub trait Event: Send + Sync {
@@ -10,23 +14,40 @@ pub trait Event: Send + Sync {
 
     fn get_name(&self) -> &'static str;
 
+    /// Stable numeric id for compact network messages, `None` unless set with `#[event(id = ...)]`.
+    fn get_wire_id_static() -> Option<u32>
+    where
+        Self: Sized;
+
+    fn get_wire_id(&self) -> Option<u32>;
+
     fn as_any_mut(&mut self) -> &mut dyn Any;
 
     fn as_any(&self) -> &dyn Any;
+
+    /// Serializes the event under its stable name, for the event log and scheduled events.
+    fn encode(&self) -> EncodeResult;
 }
 
+/// Checked by `#[derive(Event)]`, events are encoded with serde to be logged, saved and sent.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` must implement `Serialize` and `Deserialize` to derive `Event`",
+    label = "not serializable",
+    note = "add `#[derive(Serialize, Deserialize)]` next to `#[derive(Event)]`"
+)]
+pub trait SerdeEvent: Serialize + DeserializeOwned {}
+
+impl<T: Serialize + DeserializeOwned> SerdeEvent for T {}
+
 #[async_trait]
 pub trait EventHandler<E: Event>: Send + Sync {
-    async fn handle(&self, _event: &E) {
-        unimplemented!();
-    }
+    async fn handle(&self, event: &E, tick: Tick, server: Arc<PtWarServer>);
 }
 
 #[async_trait]
 pub trait AnyEventHandler: Send + Sync {
-    async fn handle_any(&self, _event: &(dyn Event)) {
-        unimplemented!();
-    }
+    async fn handle_any(&self, event: &dyn Event, tick: Tick, server: Arc<PtWarServer>);
 }
 
 struct PtWarEventHandler<E, H>
@@ -44,11 +65,221 @@ where
     E: Event + Send + Sync + 'static,
     H: EventHandler<E> + Send + Sync,
 {
-    async fn handle_any(&self, event: &(dyn Event)) {
+    async fn handle_any(&self, event: &dyn Event, tick: Tick, server: Arc<PtWarServer>) {
         if let Some(event) = event.as_any().downcast_ref::<E>() {
-            self.handler.handle(event).await;
+            self.handler.handle(event, tick, server).await;
         }
     }
 }
 
-type AnyEventHandlerMap = HashMap<TypeId, Arc<dyn AnyEventHandler>>;
+type AnyEventHandlerMap = HashMap<TypeId, Vec<Arc<dyn AnyEventHandler>>>;
+
+/// Handlers registered for each event type, in registration order.
+#[derive(Default)]
+pub struct EventRegistry {
+    handlers: AnyEventHandlerMap,
+}
+
+impl EventRegistry {
+    pub fn add_handler<E, H>(&mut self, handler: H)
+    where
+        E: Event + 'static,
+        H: EventHandler<E> + 'static,
+    {
+        let handler = PtWarEventHandler::<E, H> {
+            handler: Arc::new(handler),
+            _phantom: Default::default(),
+        };
+
+        self.handlers
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(Arc::new(handler));
+    }
+
+    /// Handlers for the concrete type of `event`, empty when none are registered.
+    pub fn handlers(&self, event: &dyn Event) -> &[Arc<dyn AnyEventHandler>] {
+        self.handlers
+            .get(&event.as_any().type_id())
+            .map_or(&[], |handlers| handlers.as_slice())
+    }
+}
+
+/// An event serialized with its name, so it can be stored and decoded later.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncodedEvent {
+    pub name: String,
+    pub payload: Vec<u8>,
+}
+
+pub type EncodeResult = bincode::Result<EncodedEvent>;
+
+impl EncodedEvent {
+    pub fn encode<E: Event + Serialize>(event: &E) -> EncodeResult {
+        Ok(Self {
+            name: E::get_name_static().to_string(),
+            payload: bincode::serialize(event)?,
+        })
+    }
+}
+
+pub type EventDecoder = fn(&[u8]) -> bincode::Result<Box<dyn Event>>;
+
+pub fn decode_event<E>(payload: &[u8]) -> bincode::Result<Box<dyn Event>>
+where
+    E: Event + DeserializeOwned + 'static,
+{
+    Ok(Box::new(bincode::deserialize::<E>(payload)?))
+}
+
+/// Entry of the global event registry, submitted by `#[derive(Event)]` for every event type.
+pub struct EventRegistration {
+    pub name: &'static str,
+    pub wire_id: Option<u32>,
+    pub decode: EventDecoder,
+}
+
+impl EventRegistration {
+    pub const fn new(name: &'static str, wire_id: Option<u32>, decode: EventDecoder) -> Self {
+        Self {
+            name,
+            wire_id,
+            decode,
+        }
+    }
+}
+
+inventory::collect!(EventRegistration);
+
+/// Decoders of every serializable event type, keyed by event name and wire id.
+///
+/// [`EventCodecs::default`] starts with every event registered by `#[derive(Event)]`,
+/// including the ones of downstream crates linked into the binary.
+pub struct EventCodecs {
+    decoders: HashMap<&'static str, EventDecoder>,
+    wire_ids: HashMap<u32, &'static str>,
+}
+
+impl Default for EventCodecs {
+    fn default() -> Self {
+        let mut codecs = Self {
+            decoders: HashMap::new(),
+            wire_ids: HashMap::new(),
+        };
+
+        for registration in inventory::iter::<EventRegistration> {
+            codecs.insert(registration.name, registration.wire_id, registration.decode);
+        }
+
+        codecs
+    }
+}
+
+impl EventCodecs {
+    /// Registers an event type by hand, only needed for events that do not use `#[derive(Event)]`.
+    pub fn register<E>(&mut self)
+    where
+        E: Event + DeserializeOwned + 'static,
+    {
+        self.insert(
+            E::get_name_static(),
+            E::get_wire_id_static(),
+            decode_event::<E>,
+        );
+    }
+
+    fn insert(&mut self, name: &'static str, wire_id: Option<u32>, decode: EventDecoder) {
+        if self.decoders.insert(name, decode).is_some() {
+            warn!("Event {} was registered twice", name);
+        }
+
+        if let Some(wire_id) = wire_id {
+            if let Some(other) = self.wire_ids.insert(wire_id, name) {
+                if other != name {
+                    error!(
+                        "Events {} and {} share the wire id {}, keeping {}",
+                        other, name, wire_id, name
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.decoders.contains_key(name)
+    }
+
+    /// Name of the event type registered under `wire_id`.
+    pub fn name_of(&self, wire_id: u32) -> Option<&'static str> {
+        self.wire_ids.get(&wire_id).copied()
+    }
+
+    /// Decodes `event`, `None` when its type was never registered.
+    pub fn decode(&self, event: &EncodedEvent) -> Option<bincode::Result<Box<dyn Event>>> {
+        self.decode_named(&event.name, &event.payload)
+    }
+
+    pub fn decode_named(
+        &self,
+        name: &str,
+        payload: &[u8],
+    ) -> Option<bincode::Result<Box<dyn Event>>> {
+        self.decoders.get(name).map(|decoder| decoder(payload))
+    }
+
+    /// Decodes a payload tagged with its wire id, `None` when no event uses that id.
+    pub fn decode_wire(
+        &self,
+        wire_id: u32,
+        payload: &[u8],
+    ) -> Option<bincode::Result<Box<dyn Event>>> {
+        self.decode_named(self.name_of(wire_id)?, payload)
+    }
+}
+
+/// Events waiting for the tick they were scheduled at.
+///
+/// Events are kept encoded so pending timers are saved along with the world.
+#[derive(Default, Serialize, Deserialize)]
+pub struct EventScheduler {
+    queue: BTreeMap<Tick, Vec<EncodedEvent>>,
+    /// Whether events were scheduled or taken since the last save.
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl EventScheduler {
+    pub fn schedule(&mut self, at_tick: Tick, event: EncodedEvent) {
+        self.queue.entry(at_tick).or_default().push(event);
+        self.changed = true;
+    }
+
+    /// Removes and returns every event due at or before `tick`, in schedule order.
+    pub fn take_due(&mut self, tick: Tick) -> Vec<EncodedEvent> {
+        let pending = self.queue.split_off(&(tick + 1));
+        let due = std::mem::replace(&mut self.queue, pending);
+
+        if !due.is_empty() {
+            self.changed = true;
+        }
+
+        due.into_values().flatten().collect()
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Forgets the changes, they are now part of a save.
+    pub fn mark_saved(&mut self) {
+        self.changed = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
@@ -1,4 +1,5 @@
 use ptwar_macros::Event;
+use serde::{Deserialize, Serialize};
 
-#[derive(Event)]
+#[derive(Event, Serialize, Deserialize)]
 pub struct EveryTickEvent;
@@ -1,8 +1,10 @@
-use crate::common::Static;
+use crate::common::{Static, StaticRegistry};
 use crate::game::GameId;
+use crate::world::state_hash::{StateHash, StateHasher};
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum RawResource {
     Iron,
     Coal,
@@ -13,7 +15,7 @@ pub enum RawResource {
     Sulfur,
 }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum ProcessedResource {
     Steel,
     Plastic,
@@ -24,12 +26,36 @@ pub enum ProcessedResource {
     Gunpowder,
 }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Resource {
     Raw(RawResource),
     Processed(ProcessedResource),
 }
 
+static RESOURCES: [Resource; 14] = [
+    Resource::Raw(RawResource::Iron),
+    Resource::Raw(RawResource::Coal),
+    Resource::Raw(RawResource::Oil),
+    Resource::Raw(RawResource::Rubber),
+    Resource::Raw(RawResource::Wood),
+    Resource::Raw(RawResource::Stone),
+    Resource::Raw(RawResource::Sulfur),
+    Resource::Processed(ProcessedResource::Steel),
+    Resource::Processed(ProcessedResource::Plastic),
+    Resource::Processed(ProcessedResource::Fuel),
+    Resource::Processed(ProcessedResource::Rubber),
+    Resource::Processed(ProcessedResource::Lumber),
+    Resource::Processed(ProcessedResource::Concrete),
+    Resource::Processed(ProcessedResource::Gunpowder),
+];
+
+impl StaticRegistry for Resource {
+    fn registry() -> &'static [Self] {
+        &RESOURCES
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct ResourceCount {
     pub resource: Static<Resource>,
     pub max: u32,
@@ -46,14 +72,16 @@ impl From<Static<Resource>> for ResourceCount {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct ResourceUpdate {
     id: GameId,
     title: String,
-    description: &'static str,
+    description: String,
     resource: Static<Resource>,
     amount: u32,
 }
 
+#[derive(Serialize, Deserialize)]
 pub enum StorageUpdateStats {
     Add(ResourceUpdate),
     Sub(ResourceUpdate),
@@ -61,6 +89,7 @@ pub enum StorageUpdateStats {
 }
 
 // TODO: Implement a proper storage system with better memory usage.
+#[derive(Serialize, Deserialize)]
 pub struct ResourceStorage {
     resources: HashMap<Static<Resource>, ResourceCount>,
     updates: BTreeMap<GameId, StorageUpdateStats>,
@@ -135,3 +164,49 @@ impl ResourceStorage {
         }
     }
 }
+
+impl StateHash for Static<Resource> {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(self.index().map_or(u64::MAX, |idx| idx as u64));
+    }
+}
+
+impl StateHash for ResourceUpdate {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_u64(self.id);
+        hasher.write_str(&self.title);
+        hasher.write_str(&self.description);
+        self.resource.hash_state(hasher);
+        hasher.write_u32(self.amount);
+    }
+}
+
+impl StateHash for ResourceStorage {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let mut resources = self.resources.values().collect::<Vec<_>>();
+        resources.sort_unstable_by_key(|count| count.resource.index());
+
+        hasher.write_u64(resources.len() as u64);
+
+        for count in resources {
+            count.resource.hash_state(hasher);
+            hasher.write_u32(count.max);
+            hasher.write_u32(count.amount);
+        }
+
+        hasher.write_u64(self.updates.len() as u64);
+
+        for (id, update) in &self.updates {
+            hasher.write_u64(*id);
+
+            let (kind, update) = match update {
+                StorageUpdateStats::Add(update) => (0, update),
+                StorageUpdateStats::Sub(update) => (1, update),
+                StorageUpdateStats::Percent(update) => (2, update),
+            };
+
+            hasher.write_u8(kind);
+            update.hash_state(hasher);
+        }
+    }
+}
@@ -1,33 +1,241 @@
+// Lets the `ptwar-macros` output, which names `::ptwar`, compile inside this crate too.
+extern crate self as ptwar;
+
+pub mod clock;
 pub mod common;
+pub mod control;
 pub mod core;
 pub mod event;
 pub mod events;
 pub mod game;
+pub mod metrics;
+pub mod rng;
+pub mod schedule;
 pub mod system;
 pub mod worker;
 pub mod world;
 
-use crate::system::{GameLoop, TPS};
-use log::info;
+use crate::control::GameLoopControl;
+use crate::core::event_log;
+use crate::core::event_log::EventLogWriter;
+use crate::core::exporter::{MetricsExporter, DEFAULT_METRICS_ADDR};
+use crate::core::save::SaveStore;
+use crate::core::{RegionEvictionSystem, SaveConfig, SaveGameSystem, StateHashSystem};
+use crate::schedule::{SystemData, SystemGraphError};
+use crate::system::{
+    EventCascade, FailurePolicy, GameLoop, MissedTicks, PtWarServer, SOrder, SystemSchedule, Tick,
+    DEFAULT_EVENT_CASCADE, DEFAULT_FAILURE_POLICY, DEFAULT_JOB_BUFFER, DEFAULT_MISSED_TICKS,
+    DEFAULT_TIMING_SUMMARY, TPS,
+};
+use crate::world::{PtWorld, WorldConfig};
+use log::{error, info};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::Mutex;
 
 use sysinfo::System;
 
+#[doc(hidden)]
+pub use async_trait;
+#[doc(hidden)]
+pub use inventory;
+
 pub const DEFAULT_TPS: TPS = 60;
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct PTWarConfig {
+    /// World generated when there is no save to resume from.
+    pub world: WorldConfig,
+    pub tps: TPS,
+    pub workers: usize,
+    /// Number of jobs that can wait for a worker before dispatching blocks.
+    pub job_buffer: usize,
+    pub save: SaveConfig,
+    pub event_cascade: EventCascade,
+    pub missed_ticks: MissedTicks,
+    pub failure_policy: FailurePolicy,
+    /// How often the slowest systems and events are logged, `None` disables the summary.
+    pub timing_summary: Option<Duration>,
+    /// Logs the world state hash every this many ticks, `None` disables it.
+    pub state_hash_every: Option<Tick>,
+    /// Unloads the unmodified regions not accessed within this many ticks, `None` keeps them all.
+    pub evict_regions_every: Option<Tick>,
+    /// Logs every dispatched event next to the saves, so a run can be replayed with [`PTWar::replay`].
+    pub event_log: bool,
+    /// How long workers get to finish their jobs on shutdown before being aborted.
+    pub shutdown_timeout: Duration,
+    /// Address of the Prometheus `/metrics` endpoint, `None` disables it.
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+impl Default for PTWarConfig {
+    fn default() -> Self {
+        Self {
+            world: WorldConfig::default(),
+            tps: DEFAULT_TPS,
+            workers: num_cpus::get(),
+            job_buffer: DEFAULT_JOB_BUFFER,
+            save: SaveConfig::default(),
+            event_cascade: DEFAULT_EVENT_CASCADE,
+            missed_ticks: DEFAULT_MISSED_TICKS,
+            failure_policy: DEFAULT_FAILURE_POLICY,
+            timing_summary: Some(DEFAULT_TIMING_SUMMARY),
+            state_hash_every: None,
+            evict_regions_every: None,
+            event_log: true,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            metrics_addr: Some(DEFAULT_METRICS_ADDR),
+        }
+    }
+}
 
 pub struct PTWar {
     pub gloop: GameLoop,
+    save_on_shutdown: bool,
+    shutdown_timeout: Duration,
+    metrics_addr: Option<SocketAddr>,
 }
 
 impl PTWar {
     pub fn new() -> Self {
-        let cpu_cores = num_cpus::get();
+        Self::with_config(PTWarConfig::default())
+    }
 
-        let gloop = GameLoop::new(cpu_cores, DEFAULT_TPS);
+    /// Starts a new world, saving it according to `config.save`.
+    pub fn with_config(config: PTWarConfig) -> Self {
+        let world = PtWorld::generate(&config.world);
 
-        PTWar { gloop }
+        // Anything failing here fails the saves as well, which get reported on their own.
+        if let Err(err) = Self::claim_save_dir(&config, None) {
+            error!(
+                "Failed to archive the saves of {}: {}",
+                config.save.dir.display(),
+                err
+            );
+        }
+
+        Self::with_server(PtWarServer::from_world(world, 0), config)
+    }
+
+    /// Resumes a game from a snapshot written by [`PtWarServer::save`].
+    ///
+    /// Saves already in `config.save.dir` are archived unless `path` is one of them.
+    pub fn from_snapshot(path: impl AsRef<Path>, config: PTWarConfig) -> io::Result<Self> {
+        let path = path.as_ref();
+        let save = SaveStore::load_snapshot(path)?;
+
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let in_dir = fs::canonicalize(parent)
+            .is_ok_and(|parent| fs::canonicalize(&config.save.dir).is_ok_and(|dir| dir == parent));
+
+        Self::claim_save_dir(&config, in_dir.then_some(save.tick))?;
+
+        Ok(Self::with_server(PtWarServer::from_save(save), config))
     }
 
-    pub async fn start(&mut self) {
+    /// Resumes from the latest save in `config.save.dir`, or starts a new world if there is none.
+    pub fn load_or_new(config: PTWarConfig) -> io::Result<Self> {
+        let store = Self::save_store(&config);
+
+        match store.load_latest()? {
+            Some(save) => {
+                Self::claim_save_dir(&config, Some(save.tick))?;
+
+                Ok(Self::with_server(PtWarServer::from_save(save), config))
+            }
+            None => Ok(Self::with_config(config)),
+        }
+    }
+
+    fn save_store(config: &PTWarConfig) -> SaveStore {
+        SaveStore::new(
+            &config.save.dir,
+            config.save.keep,
+            config.save.compact_every,
+        )
+    }
+
+    /// Archives the saves of `config.save.dir` that do not belong to a game resumed at `resume`.
+    fn claim_save_dir(config: &PTWarConfig, resume: Option<Tick>) -> io::Result<()> {
+        if config.save.interval.is_disabled() && !config.event_log {
+            return Ok(());
+        }
+
+        Self::save_store(config).claim(resume)?;
+
+        Ok(())
+    }
+
+    fn with_server(mut server: PtWarServer, config: PTWarConfig) -> Self {
+        server.saves = Self::save_store(&config);
+
+        if config.event_log {
+            server.event_log = Mutex::new(Some(EventLogWriter::new(&config.save.dir)));
+        }
+
+        let mut gloop =
+            GameLoop::with_job_buffer(server, config.workers, config.tps, config.job_buffer);
+        gloop.set_event_cascade(config.event_cascade);
+        gloop.set_missed_ticks(config.missed_ticks);
+        gloop.set_failure_policy(config.failure_policy);
+        gloop.set_timing_summary(config.timing_summary);
+
+        if let Some(every) = config.state_hash_every {
+            gloop.add_system_with(
+                SOrder::Last,
+                SystemSchedule::every(every).reads(SystemData::World),
+                StateHashSystem,
+            );
+        }
+
+        if let Some(every) = config.evict_regions_every {
+            gloop.add_system_with(
+                SOrder::Last,
+                SystemSchedule::every(every).writes(SystemData::World),
+                RegionEvictionSystem,
+            );
+        }
+
+        if !config.save.interval.is_disabled() {
+            gloop.add_system(SOrder::Last, SaveGameSystem::new(config.save.interval));
+        }
+
+        PTWar {
+            gloop,
+            save_on_shutdown: !config.save.interval.is_disabled(),
+            shutdown_timeout: config.shutdown_timeout,
+            metrics_addr: config.metrics_addr,
+        }
+    }
+
+    /// Replays the event log of the save directory from the current tick, rebuilding the
+    /// state the previous run reached after its last save.
+    ///
+    /// Event handlers must be registered before replaying, logged events without one are skipped.
+    pub async fn replay(&mut self) -> io::Result<Tick> {
+        let server = self.gloop.server();
+        let records = event_log::read_records(server.saves.dir(), server.tick().await)?;
+
+        Ok(self.gloop.replay(records).await)
+    }
+
+    pub fn control(&self) -> GameLoopControl {
+        self.gloop.control()
+    }
+
+    /// Runs the game loop until SIGINT or SIGTERM, or until shut down through [`PTWar::control`].
+    ///
+    /// On shutdown the current tick finishes, queued events are handled, the world is saved
+    /// unless saves are disabled and the workers are stopped.
+    ///
+    /// Nothing is saved when the loop fails to start, see [`GameLoop::start`].
+    pub async fn start(&mut self) -> Result<(), SystemGraphError> {
         let mut sys = System::new_all();
 
         // First we update all information of our `System` struct.
@@ -42,6 +250,63 @@ impl PTWar {
             info!("memory usage before start: {}Mb", memory / (1024 * 1024));
         }
 
-        self.gloop.start().await;
+        let control = self.control();
+
+        let signals = tokio::spawn(async move {
+            shutdown_signal().await;
+
+            info!("Shutdown signal received, stopping after the current tick");
+            control.shutdown();
+        });
+
+        let metrics = self.metrics_addr.map(|addr| {
+            let exporter = MetricsExporter::new(self.gloop.server(), self.gloop.worker_probe());
+
+            tokio::spawn(async move {
+                if let Err(err) = exporter.serve(addr).await {
+                    error!("Failed to serve metrics on {}: {}", addr, err);
+                }
+            })
+        });
+
+        let started = self.gloop.start().await;
+
+        signals.abort();
+
+        if let Some(metrics) = metrics {
+            metrics.abort();
+        }
+
+        started?;
+
+        self.gloop
+            .shutdown(self.save_on_shutdown, self.shutdown_timeout)
+            .await;
+
+        Ok(())
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+
+                return;
+            }
+            Err(err) => error!("Failed to listen for SIGTERM: {}", err),
+        }
+    }
+
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for SIGINT: {}", err);
+        std::future::pending::<()>().await;
     }
 }
@@ -0,0 +1,225 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+/// Number of recent samples percentiles are computed over.
+const TIMING_WINDOW: usize = 1024;
+
+/// What a timing was measured for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimingKey {
+    /// A system, by [`TickHandler::name`](crate::worker::TickHandler::name).
+    System(&'static str),
+    /// A handler of an event type, by event name.
+    Event(&'static str),
+}
+
+impl TimingKey {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TimingKey::System(_) => "system",
+            TimingKey::Event(_) => "event",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimingKey::System(name) | TimingKey::Event(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for TimingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimingKey::System(name) => write!(f, "system {}", name),
+            TimingKey::Event(name) => write!(f, "event {}", name),
+        }
+    }
+}
+
+/// Run times of one system or event type, percentiles cover the last samples only.
+#[derive(Debug, Clone, Default)]
+pub struct Timing {
+    samples: VecDeque<Duration>,
+    last: Duration,
+    max: Duration,
+    count: u64,
+    total: Duration,
+}
+
+impl Timing {
+    pub fn record(&mut self, elapsed: Duration) {
+        if self.samples.len() == TIMING_WINDOW {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(elapsed);
+        self.last = elapsed;
+        self.max = self.max.max(elapsed);
+        self.count += 1;
+        self.total += elapsed;
+    }
+
+    pub fn last(&self) -> Duration {
+        self.last
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of every run time recorded, not only the last samples.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// `quantile` goes from `0.0` to `1.0`, e.g. `0.99` for the p99.
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let mut samples = self.samples.iter().copied().collect::<Vec<_>>();
+        samples.sort_unstable();
+
+        let idx = ((samples.len() - 1) as f64 * quantile.clamp(0.0, 1.0)).round() as usize;
+
+        samples[idx]
+    }
+
+    pub fn p50(&self) -> Duration {
+        self.percentile(0.5)
+    }
+
+    pub fn p99(&self) -> Duration {
+        self.percentile(0.99)
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "last: {:?} p50: {:?} p99: {:?} max: {:?} runs: {}",
+            self.last,
+            self.p50(),
+            self.p99(),
+            self.max,
+            self.count
+        )
+    }
+}
+
+/// Timings of every system and event type, recorded by the workers.
+#[derive(Debug, Default)]
+pub struct TimingStats {
+    pub timings: HashMap<TimingKey, Timing>,
+    /// Slowest job of the last tick, its timings are merged at the end of every tick.
+    pub slowest_in_tick: Option<(TimingKey, Duration)>,
+    /// Number of runs that panicked, failed runs are not part of the timings.
+    pub failures: HashMap<TimingKey, u64>,
+}
+
+impl TimingStats {
+    pub fn record(&mut self, key: TimingKey, elapsed: Duration) {
+        self.timings.entry(key).or_default().record(elapsed);
+
+        if self
+            .slowest_in_tick
+            .is_none_or(|(_, slowest)| elapsed > slowest)
+        {
+            self.slowest_in_tick = Some((key, elapsed));
+        }
+    }
+
+    /// Counts a failed run of `key`, returns its number of failures so far.
+    pub fn record_failure(&mut self, key: TimingKey) -> u64 {
+        let failures = self.failures.entry(key).or_default();
+        *failures += 1;
+
+        *failures
+    }
+
+    pub fn get(&self, key: &TimingKey) -> Option<&Timing> {
+        self.timings.get(key)
+    }
+
+    /// Timings sorted by p99, slowest first.
+    pub fn slowest(&self) -> Vec<(TimingKey, &Timing)> {
+        let mut timings = self
+            .timings
+            .iter()
+            .map(|(key, timing)| (*key, timing, timing.p99()))
+            .collect::<Vec<_>>();
+
+        timings.sort_by_key(|(_, _, p99)| Reverse(*p99));
+
+        timings
+            .into_iter()
+            .map(|(key, timing, _)| (key, timing))
+            .collect()
+    }
+}
+
+/// Upper bounds in seconds of the tick duration buckets.
+pub const TICK_DURATION_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.016, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Durations counted per bucket, buckets are cumulative as in Prometheus histograms.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(&TICK_DURATION_BUCKETS)
+    }
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    /// `(upper bound, samples at or below it)` for every bucket.
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        self.bounds.iter().copied().zip(self.counts.iter().copied())
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
@@ -0,0 +1,66 @@
+//! Seeded random streams, every random draw of the simulation goes through one of these so a
+//! world and its ticks play out the same from the same seed.
+
+use crate::system::Tick;
+use crate::world::state_hash::StateHasher;
+use hexx::Hex;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Random generator of the simulation, never use `rand::thread_rng` in gameplay code.
+///
+/// A named algorithm rather than `StdRng`, which may change its stream between `rand` releases.
+pub type SimRng = ChaCha8Rng;
+
+const REGION_STREAM: u64 = 1;
+const TICK_STREAM: u64 = 2;
+
+/// Stream of a region, used while generating it.
+pub fn region_rng(seed: u32, hex: Hex) -> SimRng {
+    stream(&[
+        seed as u64,
+        REGION_STREAM,
+        hex.x as u32 as u64,
+        hex.y as u32 as u64,
+    ])
+}
+
+/// Stream named `name` at `tick`, e.g. one per system so systems don't draw from each other's.
+pub fn tick_rng(seed: u32, tick: Tick, name: &str) -> SimRng {
+    stream(&[
+        seed as u64,
+        TICK_STREAM,
+        tick as u64,
+        (tick >> 64) as u64,
+        name_hash(name),
+    ])
+}
+
+fn name_hash(name: &str) -> u64 {
+    let mut hasher = StateHasher::default();
+    hasher.write(name.as_bytes());
+    hasher.finish()
+}
+
+fn stream(words: &[u64]) -> SimRng {
+    let mut state = words
+        .iter()
+        .fold(0u64, |state, word| splitmix64(state ^ splitmix64(*word)));
+
+    // Expanded by hand, `seed_from_u64` leaves the expansion up to `rand_core`.
+    let mut seed = [0u8; 32];
+
+    for chunk in seed.chunks_exact_mut(8) {
+        state = splitmix64(state);
+        chunk.copy_from_slice(&state.to_le_bytes());
+    }
+
+    SimRng::from_seed(seed)
+}
+
+fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
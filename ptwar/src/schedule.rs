@@ -0,0 +1,324 @@
+use crate::system::SOrder;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Shared data a system reads or writes, systems writing the same data never run concurrently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemData {
+    World,
+    Stats,
+    Events,
+}
+
+impl SystemData {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Data declared by a system, systems that declare nothing are assumed to touch no shared state.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemAccess {
+    reads: u8,
+    writes: u8,
+}
+
+impl SystemAccess {
+    pub fn read(mut self, data: SystemData) -> Self {
+        self.reads |= data.bit();
+        self
+    }
+
+    pub fn write(mut self, data: SystemData) -> Self {
+        self.writes |= data.bit();
+        self
+    }
+
+    pub fn conflicts(&self, other: &SystemAccess) -> bool {
+        self.writes & (other.reads | other.writes) != 0 || other.writes & self.reads != 0
+    }
+}
+
+/// What the graph needs to know about a registered system.
+pub struct SystemNode {
+    pub name: &'static str,
+    pub order: SOrder,
+    pub before: Vec<&'static str>,
+    pub after: Vec<&'static str>,
+    pub access: SystemAccess,
+}
+
+#[derive(Debug)]
+pub enum SystemGraphError {
+    /// `system` declared an ordering against a system that was never registered.
+    UnknownSystem {
+        system: &'static str,
+        dependency: &'static str,
+    },
+    /// Systems whose `before`/`after` constraints form a cycle.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for SystemGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemGraphError::UnknownSystem { system, dependency } => {
+                write!(
+                    f,
+                    "system {} is ordered against unknown system {}",
+                    system, dependency
+                )
+            }
+            SystemGraphError::Cycle(systems) => {
+                write!(f, "systems form a dependency cycle: {}", systems.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SystemGraphError {}
+
+/// Dependency graph of the systems of a [`GameLoop`](crate::system::GameLoop), built at startup.
+///
+/// A system runs once everything it depends on finished, systems of an [`SOrder`] stage
+/// depend on every system of the previous stage.
+pub struct SystemGraph {
+    dependencies: Vec<usize>,
+    dependents: Vec<Vec<usize>>,
+    access: Vec<SystemAccess>,
+    roots: Vec<usize>,
+}
+
+impl SystemGraph {
+    pub fn build(nodes: &[SystemNode]) -> Result<Self, SystemGraphError> {
+        let mut by_name: HashMap<&'static str, Vec<usize>> = HashMap::new();
+
+        for (idx, node) in nodes.iter().enumerate() {
+            by_name.entry(node.name).or_default().push(idx);
+        }
+
+        let mut edges = Vec::new();
+
+        for (idx, node) in nodes.iter().enumerate() {
+            for (dependency, before) in node
+                .after
+                .iter()
+                .map(|name| (name, false))
+                .chain(node.before.iter().map(|name| (name, true)))
+            {
+                let Some(others) = by_name.get(dependency) else {
+                    return Err(SystemGraphError::UnknownSystem {
+                        system: node.name,
+                        dependency,
+                    });
+                };
+
+                for &other in others {
+                    edges.push(if before { (idx, other) } else { (other, idx) });
+                }
+            }
+        }
+
+        let mut previous_stage: Vec<usize> = Vec::new();
+
+        for order in SOrder::order() {
+            let stage = (0..nodes.len())
+                .filter(|&idx| nodes[idx].order == *order)
+                .collect::<Vec<_>>();
+
+            if stage.is_empty() {
+                continue;
+            }
+
+            for &from in &previous_stage {
+                edges.extend(stage.iter().map(|&to| (from, to)));
+            }
+
+            previous_stage = stage;
+        }
+
+        let mut dependencies = vec![0; nodes.len()];
+        let mut dependents = vec![Vec::new(); nodes.len()];
+
+        edges.sort_unstable();
+        edges.dedup();
+
+        for (from, to) in edges {
+            dependencies[to] += 1;
+            dependents[from].push(to);
+        }
+
+        let roots = (0..nodes.len())
+            .filter(|&idx| dependencies[idx] == 0)
+            .collect::<Vec<_>>();
+
+        let graph = SystemGraph {
+            dependencies,
+            dependents,
+            access: nodes.iter().map(|node| node.access).collect(),
+            roots,
+        };
+
+        graph.check_cycles(nodes)?;
+
+        Ok(graph)
+    }
+
+    fn check_cycles(&self, nodes: &[SystemNode]) -> Result<(), SystemGraphError> {
+        let mut run = self.start();
+
+        while let Some(idx) = run.ready.pop() {
+            run.complete(self, idx);
+        }
+
+        let blocked = (0..nodes.len())
+            .filter(|&idx| run.dependencies[idx] > 0)
+            .map(|idx| nodes[idx].name)
+            .collect::<Vec<_>>();
+
+        if blocked.is_empty() {
+            Ok(())
+        } else {
+            Err(SystemGraphError::Cycle(blocked))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dependencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dependencies.is_empty()
+    }
+
+    pub fn access(&self, idx: usize) -> &SystemAccess {
+        &self.access[idx]
+    }
+
+    /// Starts walking the graph for one tick.
+    pub fn start(&self) -> GraphRun {
+        GraphRun {
+            dependencies: self.dependencies.clone(),
+            ready: self.roots.clone(),
+        }
+    }
+}
+
+/// Progress of one walk over a [`SystemGraph`].
+pub struct GraphRun {
+    dependencies: Vec<usize>,
+    /// Systems whose dependencies all completed.
+    pub ready: Vec<usize>,
+}
+
+impl GraphRun {
+    /// Marks `idx` as done, making its dependents ready once all their dependencies are.
+    pub fn complete(&mut self, graph: &SystemGraph, idx: usize) {
+        for &dependent in &graph.dependents[idx] {
+            self.dependencies[dependent] -= 1;
+
+            if self.dependencies[dependent] == 0 {
+                self.ready.push(dependent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &'static str, order: SOrder) -> SystemNode {
+        SystemNode {
+            name,
+            order,
+            before: Vec::new(),
+            after: Vec::new(),
+            access: SystemAccess::default(),
+        }
+    }
+
+    /// System names in the order a one-at-a-time walk of the graph runs them.
+    fn walk(graph: &SystemGraph, nodes: &[SystemNode]) -> Vec<&'static str> {
+        let mut run = graph.start();
+        let mut names = Vec::new();
+
+        while let Some(idx) = run.ready.pop() {
+            names.push(nodes[idx].name);
+            run.complete(graph, idx);
+        }
+
+        names
+    }
+
+    #[test]
+    fn stages_and_constraints_order_systems() {
+        let mut physics = node("physics", SOrder::Second);
+        physics.after.push("ai");
+        let mut ai = node("ai", SOrder::Second);
+        ai.after.push("input");
+
+        let nodes = [
+            node("save", SOrder::Last),
+            physics,
+            node("input", SOrder::First),
+            ai,
+            node("render", SOrder::Third),
+        ];
+
+        let graph = SystemGraph::build(&nodes).unwrap();
+
+        assert_eq!(
+            walk(&graph, &nodes),
+            ["input", "ai", "physics", "render", "save"]
+        );
+    }
+
+    #[test]
+    fn unknown_dependency_is_rejected() {
+        let mut ai = node("ai", SOrder::First);
+        ai.before.push("missing");
+
+        let err = SystemGraph::build(&[ai]).err().unwrap();
+
+        assert!(matches!(
+            err,
+            SystemGraphError::UnknownSystem {
+                system: "ai",
+                dependency: "missing"
+            }
+        ));
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut ai = node("ai", SOrder::First);
+        ai.after.push("physics");
+        let mut physics = node("physics", SOrder::First);
+        physics.after.push("ai");
+
+        let err = SystemGraph::build(&[ai, physics, node("save", SOrder::Last)])
+            .err()
+            .unwrap();
+
+        let SystemGraphError::Cycle(mut systems) = err else {
+            panic!("expected a cycle, got {}", err);
+        };
+        systems.sort_unstable();
+
+        // Systems waiting on the cycle are reported along with it.
+        assert_eq!(systems, ["ai", "physics", "save"]);
+    }
+
+    #[test]
+    fn constraint_against_stage_order_is_a_cycle() {
+        let mut input = node("input", SOrder::First);
+        input.after.push("save");
+
+        let err = SystemGraph::build(&[input, node("save", SOrder::Last)])
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, SystemGraphError::Cycle(_)));
+    }
+}
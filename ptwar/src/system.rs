@@ -1,17 +1,47 @@
-use crate::event::Event;
+use crate::clock::{Clock, SystemClock};
+use crate::control::{ControlCommand, GameLoopControl, MAX_SPEED, MIN_SPEED};
+use crate::core::event_log::{EventLogWriter, EventRecord};
+use crate::core::save::{SaveStore, SavedGame};
+use crate::core::{DEFAULT_SAVE_COMPACT_EVERY, DEFAULT_SAVE_DIR, DEFAULT_SAVE_KEEP};
+use crate::event::{EncodedEvent, Event, EventCodecs, EventHandler, EventRegistry, EventScheduler};
+use crate::metrics::{Histogram, TimingKey, TimingStats};
+use crate::rng::{tick_rng, SimRng};
+use crate::schedule::{SystemAccess, SystemData, SystemGraph, SystemGraphError, SystemNode};
 use crate::system::SOrder::{First, Second};
-use crate::worker::{PWorkerManager, TickHandler, WorkerJob};
+use crate::worker::{PWorkerManager, TickHandler, WorkerJob, WorkerProbe};
 use crate::world::PtWorld;
-use log::{info, warn};
-use std::collections::HashMap;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::{Mutex, RwLock};
+use tokio::time::{Interval, MissedTickBehavior};
 
 pub struct ServerStats {
     pub tick: u128,
     pub last_tick: Instant,
     pub last_save: Option<(u128, Instant)>,
+    /// Ticks the loop is currently running behind schedule.
+    pub ticks_behind: u128,
+    /// Ticks skipped or delayed since start because the loop could not keep up.
+    pub missed_ticks: u128,
+    pub paused: bool,
+    pub timings: TimingStats,
+    pub tick_durations: Histogram,
+    /// Ticks per second over the last second, and the rate the loop aims for.
+    pub tps: f64,
+    pub target_tps: f64,
+    /// Events dispatched since start, and during the last tick.
+    pub events_processed: u64,
+    pub tick_events: usize,
 }
 
 impl Default for ServerStats {
@@ -20,6 +50,15 @@ impl Default for ServerStats {
             tick: 0,
             last_tick: Instant::now(),
             last_save: None,
+            ticks_behind: 0,
+            missed_ticks: 0,
+            paused: false,
+            timings: Default::default(),
+            tick_durations: Default::default(),
+            tps: 0.0,
+            target_tps: 0.0,
+            events_processed: 0,
+            tick_events: 0,
         }
     }
 }
@@ -29,18 +68,64 @@ pub struct PtWarServer {
     pub events_queue: Arc<Mutex<Vec<Box<dyn Event>>>>,
     pub world: Arc<RwLock<PtWorld>>,
     pub stats: Arc<RwLock<ServerStats>>,
+    pub scheduled_events: Arc<Mutex<EventScheduler>>,
+    pub event_codecs: Arc<std::sync::RwLock<EventCodecs>>,
+    pub event_log: Mutex<Option<EventLogWriter>>,
+    pub saves: SaveStore,
+    save_requested: AtomicBool,
+    clock: Arc<dyn Clock>,
 }
 
 impl PtWarServer {
     pub fn new() -> Self {
+        Self::from_world(PtWorld::from_seed(0), 0)
+    }
+
+    /// Creates a server that resumes a game read back from a [`SaveStore`].
+    pub fn from_save(save: SavedGame) -> Self {
+        let mut server = Self::from_world(save.world, save.tick);
+        server.scheduled_events = Arc::new(Mutex::new(save.scheduled_events));
+
+        server
+    }
+
+    /// Creates a server that resumes `world` at `tick`, e.g. after [`PtWorld::load`].
+    pub fn from_world(world: PtWorld, tick: Tick) -> Self {
+        let stats = ServerStats {
+            tick,
+            last_save: world.last_save,
+            ..Default::default()
+        };
+
         Self {
-            tick: Arc::new(RwLock::new(0)),
+            tick: Arc::new(RwLock::new(tick)),
             events_queue: Default::default(),
-            world: Arc::new(RwLock::new(PtWorld::from_seed(0)),),
-            stats: Default::default(),
+            world: Arc::new(RwLock::new(world)),
+            stats: Arc::new(RwLock::new(stats)),
+            scheduled_events: Default::default(),
+            event_codecs: Default::default(),
+            event_log: Default::default(),
+            saves: SaveStore::new(
+                DEFAULT_SAVE_DIR,
+                DEFAULT_SAVE_KEEP,
+                DEFAULT_SAVE_COMPACT_EVERY,
+            ),
+            save_requested: AtomicBool::new(false),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Replaces the wall clock, e.g. with a [`ManualClock`](crate::clock::ManualClock) in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Current game time, use this instead of `Instant::now` in systems.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
     pub async fn tick(&self) -> Tick {
         self.tick.read().await.clone()
     }
@@ -48,6 +133,10 @@ impl PtWarServer {
     pub async fn next_tick(&self) {
         let mut tick = self.tick.write().await;
         *tick += 1;
+
+        let mut stats = self.stats.write().await;
+        stats.tick = *tick;
+        stats.last_tick = self.now();
     }
 
     pub async fn add_event(&self, event: impl Event + 'static) {
@@ -56,14 +145,138 @@ impl PtWarServer {
         queue.push(Box::new(event));
     }
 
-    // TODO: implement save method
-    pub async fn save(&self) {
-        let world = self.world.write().await;
+    /// Random stream of `name` for the current tick, derived from the world seed.
+    ///
+    /// Gameplay code draws from here so a run is reproducible from its seed, give each
+    /// system its own `name` so they don't depend on each other's draws.
+    pub async fn rng(&self, name: &str) -> SimRng {
+        let seed = self.world.read().await.seed;
+
+        tick_rng(seed, self.tick().await, name)
+    }
+
+    /// Makes events of type `E` decodable, required for scheduled events restored from a save
+    /// and for replaying the event log. Events using `#[derive(Event)]` are registered already.
+    pub fn register_event<E>(&self)
+    where
+        E: Event + DeserializeOwned + 'static,
+    {
+        let registered = self
+            .event_codecs
+            .read()
+            .unwrap()
+            .contains(E::get_name_static());
+
+        if !registered {
+            self.event_codecs.write().unwrap().register::<E>();
+        }
+    }
+
+    /// Queues `event` for the event phase of `at_tick`.
+    ///
+    /// Due events are taken before the systems run, so an event scheduled for the current tick,
+    /// or for one that already passed, fires in the event phase of the next tick.
+    pub async fn schedule_event<E>(&self, event: E, at_tick: Tick)
+    where
+        E: Event + Serialize + DeserializeOwned + 'static,
+    {
+        let encoded = match EncodedEvent::encode(&event) {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                error!(
+                    "Failed to encode scheduled event {}: {}",
+                    event.get_name(),
+                    err
+                );
+                return;
+            }
+        };
+
+        self.register_event::<E>();
+
+        self.scheduled_events
+            .lock()
+            .await
+            .schedule(at_tick, encoded);
+    }
+
+    /// Queues `event` for the event phase `ticks` ticks from now.
+    pub async fn schedule_in<E>(&self, event: E, ticks: Tick)
+    where
+        E: Event + Serialize + DeserializeOwned + 'static,
+    {
+        let at_tick = self.tick().await + ticks;
 
-        self.stats.write().await.last_save = Some((self.tick().await, Instant::now()));
+        self.schedule_event(event, at_tick).await;
+    }
+
+    /// Moves every scheduled event due this tick into the events queue.
+    pub async fn fire_scheduled_events(&self) {
+        let tick = self.tick().await;
+
+        let due = self.scheduled_events.lock().await.take_due(tick);
+
+        if due.is_empty() {
+            return;
+        }
+
+        let mut queue = self.events_queue.lock().await;
+        let codecs = self.event_codecs.read().unwrap();
+
+        for encoded in due {
+            match codecs.decode(&encoded) {
+                Some(Ok(event)) => queue.push(event),
+                Some(Err(err)) => {
+                    error!(
+                        "tick: {} failed to decode scheduled event {}: {}",
+                        tick, encoded.name, err
+                    );
+                }
+                None => {
+                    error!(
+                        "tick: {} scheduled event {} was never registered, dropping it",
+                        tick, encoded.name
+                    );
+                }
+            }
+        }
+    }
+
+    /// Asks the game loop to save once the current tick is over.
+    pub fn request_save(&self) {
+        self.save_requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn take_save_request(&self) -> bool {
+        self.save_requested.swap(false, Ordering::SeqCst)
+    }
+
+    /// Writes the world to the [`SaveStore`], as a delta of the previous save when possible,
+    /// and starts a new event log segment.
+    ///
+    /// Holds the world write lock for the whole save so no system mutates it mid-snapshot.
+    /// Systems should call [`PtWarServer::request_save`] instead, so the save lands between two ticks.
+    pub async fn save(&self) -> io::Result<()> {
+        let mut world = self.world.write().await;
+        let mut scheduled_events = self.scheduled_events.lock().await;
+        let tick = self.tick().await;
+
+        self.saves.write(&mut world, &mut scheduled_events, tick)?;
+
+        if let Some(event_log) = self.event_log.lock().await.as_mut() {
+            event_log.roll()?;
+        }
+
+        let last_save = Some((tick, self.now()));
+
+        world.last_save = last_save;
+        self.stats.write().await.last_save = last_save;
+
+        Ok(())
     }
 }
 
+/// Stage of a tick a system runs in, every stage finishes before the next one starts.
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
 pub enum SOrder {
     First,
@@ -73,110 +286,1014 @@ pub enum SOrder {
 }
 
 impl SOrder {
-    fn order() -> impl Iterator<Item = &'static SOrder> {
+    pub(crate) fn order() -> impl Iterator<Item = &'static SOrder> {
         static ORDERS: [SOrder; 4] = [First, Second, SOrder::Third, SOrder::Last];
         ORDERS.iter()
     }
 }
 
+/// How events emitted while handling other events are processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventCascade {
+    /// Follow-up events wait for the next tick's event phase.
+    NextTick,
+    /// Follow-up events are handled in extra sub-phases of the same tick, up to a max depth.
+    /// Events still queued after the last sub-phase are deferred to the next tick.
+    SameTick(usize),
+}
+
+pub const DEFAULT_EVENT_CASCADE: EventCascade = EventCascade::SameTick(8);
+
+/// What the loop does with the ticks it could not start on time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTicks {
+    /// Runs the missed ticks back to back until the loop is on schedule again.
+    Burst,
+    /// Drops the missed ticks and waits for the next tick of the original schedule.
+    Skip,
+    /// Starts the next tick a full tick after the late one, shifting the schedule.
+    Delay,
+}
+
+impl From<MissedTicks> for MissedTickBehavior {
+    fn from(missed_ticks: MissedTicks) -> Self {
+        match missed_ticks {
+            MissedTicks::Burst => MissedTickBehavior::Burst,
+            MissedTicks::Skip => MissedTickBehavior::Skip,
+            MissedTicks::Delay => MissedTickBehavior::Delay,
+        }
+    }
+}
+
+pub const DEFAULT_MISSED_TICKS: MissedTicks = MissedTicks::Skip;
+
+/// What the loop does with a system whose runs keep panicking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Keeps running the system, every failure is logged and counted.
+    Keep,
+    /// Stops running the system after this many failed runs in a row.
+    DisableAfter(u32),
+}
+
+pub const DEFAULT_FAILURE_POLICY: FailurePolicy = FailurePolicy::Keep;
+
+pub const DEFAULT_JOB_BUFFER: usize = 2048;
+
+/// Shortest time between two ticks whatever the tps and speed, the tick interval can not be zero.
+pub const MIN_TICK_DURATION: Duration = Duration::from_micros(100);
+
+pub const DEFAULT_TIMING_SUMMARY: Duration = Duration::from_secs(60);
+const TIMING_SUMMARY_LEN: usize = 10;
+
 pub type TPS = u8;
 
 pub type Tick = u128;
 
+/// How often a system registered on the [`GameLoop`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cadence {
+    EveryTick,
+    /// On ticks that are a multiple of the given number.
+    Ticks(Tick),
+    /// At most once per interval of wall-clock time, replays do not reproduce it.
+    Time(Duration),
+}
+
+/// Checked before dispatching a system, the system is skipped when it resolves to `false`.
+pub type RunCondition =
+    Box<dyn Fn(Tick, Arc<PtWarServer>) -> BoxFuture<'static, bool> + Send + Sync>;
+
+/// Cadence, run conditions, ordering and data access of a system.
+///
+/// Cadence and conditions are checked by the loop before dispatching the system, `before`/`after`
+/// and the declared access place it in the [`SystemGraph`] built when the loop starts.
+pub struct SystemSchedule {
+    cadence: Cadence,
+    conditions: Vec<RunCondition>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    access: SystemAccess,
+}
+
+impl Default for SystemSchedule {
+    fn default() -> Self {
+        Self::new(Cadence::EveryTick)
+    }
+}
+
+impl SystemSchedule {
+    pub fn new(cadence: Cadence) -> Self {
+        Self {
+            cadence,
+            conditions: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            access: SystemAccess::default(),
+        }
+    }
+
+    pub fn every(ticks: Tick) -> Self {
+        Self::new(Cadence::Ticks(ticks.max(1)))
+    }
+
+    pub fn every_duration(interval: Duration) -> Self {
+        Self::new(Cadence::Time(interval))
+    }
+
+    /// Only runs the system on ticks where `condition` holds, on top of its cadence.
+    ///
+    /// Conditions are awaited by the loop while other systems may be running,
+    /// they should only take short locks on the server state.
+    pub fn run_if<F, Fut>(mut self, condition: F) -> Self
+    where
+        F: Fn(Tick, Arc<PtWarServer>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.conditions.push(Box::new(move |tick, server| {
+            condition(tick, server).boxed()
+        }));
+        self
+    }
+
+    /// Runs the system before every system named `system`, see [`TickHandler::name`].
+    pub fn before(mut self, system: &'static str) -> Self {
+        self.before.push(system);
+        self
+    }
+
+    /// Runs the system after every system named `system`, see [`TickHandler::name`].
+    pub fn after(mut self, system: &'static str) -> Self {
+        self.after.push(system);
+        self
+    }
+
+    pub fn reads(mut self, data: SystemData) -> Self {
+        self.access = self.access.read(data);
+        self
+    }
+
+    pub fn writes(mut self, data: SystemData) -> Self {
+        self.access = self.access.write(data);
+        self
+    }
+
+    pub fn cadence(&self) -> Cadence {
+        self.cadence
+    }
+}
+
+/// A system registered on the [`GameLoop`] with its schedule.
+struct GameSystem {
+    handler: Arc<Box<dyn TickHandler>>,
+    order: SOrder,
+    schedule: SystemSchedule,
+    last_run: Option<Instant>,
+    /// Failed runs in a row.
+    failures: u32,
+    disabled: bool,
+}
+
+impl GameSystem {
+    async fn should_run(&mut self, tick: Tick, server: &Arc<PtWarServer>, now: Instant) -> bool {
+        if self.disabled {
+            return false;
+        }
+
+        let due = match self.schedule.cadence {
+            Cadence::EveryTick => true,
+            Cadence::Ticks(every) => tick.is_multiple_of(every.max(1)),
+            Cadence::Time(interval) => self
+                .last_run
+                .is_none_or(|last_run| now.duration_since(last_run) >= interval),
+        };
+
+        if !due {
+            return false;
+        }
+
+        for condition in &self.schedule.conditions {
+            if !condition(tick, server.clone()).await {
+                return false;
+            }
+        }
+
+        self.last_run = Some(now);
+
+        true
+    }
+
+    fn record_run(&mut self, succeeded: bool, policy: FailurePolicy) {
+        if succeeded {
+            self.failures = 0;
+            return;
+        }
+
+        self.failures += 1;
+
+        if let FailurePolicy::DisableAfter(limit) = policy {
+            if self.failures >= limit.max(1) {
+                self.disabled = true;
+
+                error!(
+                    "Disabling system {} after {} failed runs in a row",
+                    self.handler.name(),
+                    self.failures
+                );
+            }
+        }
+    }
+}
+
 pub struct GameLoop {
     server: Arc<PtWarServer>,
     tps: TPS,
     tick_duration: Duration,
+    speed: f64,
+    paused: bool,
     last_tick: Instant,
+    missed_ticks: MissedTicks,
+    timing_summary: Option<Duration>,
+    last_timing_summary: Instant,
+    tps_sample: (Instant, u64),
+    control_tx: UnboundedSender<ControlCommand>,
+    control_rx: Option<UnboundedReceiver<ControlCommand>>,
     workers_count: usize,
     manager: PWorkerManager,
-    systems: HashMap<SOrder, Vec<Arc<Box<dyn TickHandler>>>>,
+    systems: Vec<GameSystem>,
+    system_graph: Option<SystemGraph>,
+    failure_policy: FailurePolicy,
+    events: EventRegistry,
+    event_cascade: EventCascade,
 }
 
 impl GameLoop {
     pub fn new(workers_count: usize, tps: TPS) -> Self {
-        let server = Arc::new(PtWarServer::new());
+        Self::with_server(PtWarServer::new(), workers_count, tps)
+    }
+
+    pub fn with_server(server: PtWarServer, workers_count: usize, tps: TPS) -> Self {
+        Self::with_job_buffer(server, workers_count, tps, DEFAULT_JOB_BUFFER)
+    }
 
-        let manager = PWorkerManager::new(server.clone(), workers_count, 2048);
+    /// `job_buffer` is the number of jobs that can wait for a worker before dispatching blocks.
+    pub fn with_job_buffer(
+        server: PtWarServer,
+        workers_count: usize,
+        tps: TPS,
+        job_buffer: usize,
+    ) -> Self {
+        let server = Arc::new(server);
 
-        let tick_duration = Duration::from_secs(1) / tps as u32;
+        let manager = PWorkerManager::new(server.clone(), workers_count, job_buffer);
+
+        let tick_duration = (Duration::from_secs(1) / tps as u32).max(MIN_TICK_DURATION);
+
+        let (control_tx, control_rx) = tokio::sync::mpsc::unbounded_channel();
 
         GameLoop {
             server,
             tps,
             tick_duration,
+            speed: 1.0,
+            paused: false,
             last_tick: Instant::now(),
+            missed_ticks: DEFAULT_MISSED_TICKS,
+            timing_summary: Some(DEFAULT_TIMING_SUMMARY),
+            last_timing_summary: Instant::now(),
+            tps_sample: (Instant::now(), 0),
+            control_tx,
+            control_rx: Some(control_rx),
             workers_count,
             manager,
             systems: Default::default(),
+            system_graph: None,
+            failure_policy: DEFAULT_FAILURE_POLICY,
+            events: Default::default(),
+            event_cascade: DEFAULT_EVENT_CASCADE,
         }
     }
 
+    /// Time between two ticks, with the speed multiplier applied.
     pub fn tick_duration(&self) -> Duration {
         self.tick_duration
     }
 
+    pub fn tps(&self) -> TPS {
+        self.tps
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn control(&self) -> GameLoopControl {
+        GameLoopControl::new(self.control_tx.clone())
+    }
+
     pub fn workers_count(&self) -> usize {
         self.workers_count
     }
 
-    pub async fn start(&mut self) {
+    pub fn event_cascade(&self) -> EventCascade {
+        self.event_cascade
+    }
+
+    pub fn set_event_cascade(&mut self, event_cascade: EventCascade) {
+        self.event_cascade = event_cascade;
+    }
+
+    pub fn missed_ticks(&self) -> MissedTicks {
+        self.missed_ticks
+    }
+
+    pub fn set_missed_ticks(&mut self, missed_ticks: MissedTicks) {
+        self.missed_ticks = missed_ticks;
+    }
+
+    pub fn failure_policy(&self) -> FailurePolicy {
+        self.failure_policy
+    }
+
+    pub fn set_failure_policy(&mut self, failure_policy: FailurePolicy) {
+        self.failure_policy = failure_policy;
+    }
+
+    pub fn timing_summary(&self) -> Option<Duration> {
+        self.timing_summary
+    }
+
+    /// Sets how often the slowest systems and events are logged, `None` disables the summary.
+    pub fn set_timing_summary(&mut self, timing_summary: Option<Duration>) {
+        self.timing_summary = timing_summary;
+    }
+
+    pub fn worker_probe(&self) -> WorkerProbe {
+        self.manager.probe()
+    }
+
+    pub fn server(&self) -> Arc<PtWarServer> {
+        self.server.clone()
+    }
+
+    /// Runs the loop until shut down through [`GameLoop::control`].
+    ///
+    /// Fails right away when the registered systems have a dependency cycle
+    /// or depend on a system that is not registered.
+    pub async fn start(&mut self) -> Result<(), SystemGraphError> {
         info!(
             "Starting game loop tps: {}, tick on: {}ms",
             self.tps,
             self.tick_duration.as_millis()
         );
 
+        self.build_system_graph()?;
+
+        let Some(mut control) = self.control_rx.take() else {
+            error!("Game loop is already running");
+            return Ok(());
+        };
+
+        let mut ticker = self.ticker();
+        let mut previous = None;
+
         loop {
-            let elapsed = Instant::now().duration_since(self.last_tick);
+            tokio::select! {
+                scheduled = ticker.tick(), if !self.paused => {
+                    let scheduled = scheduled.into_std();
 
-            if elapsed < self.tick_duration {
-                continue;
-            }
+                    self.account_missed_ticks(scheduled, previous).await;
+                    previous = Some(scheduled);
+
+                    self.live_tick().await;
+                }
+                Some(command) = control.recv() => {
+                    if command == ControlCommand::Shutdown {
+                        break;
+                    }
 
-            let start_process = Instant::now();
+                    // Pauses and rate changes restart the schedule, so they are not counted as missed ticks.
+                    previous = None;
 
-            // Process tick
-            for x in SOrder::order() {
-                if let Some(systems) = self.systems.get(x) {
-                    for system in systems {
-                        self.manager.send(WorkerJob::Tick(system.clone())).await;
+                    if self.apply_control(command).await {
+                        ticker = self.ticker();
                     }
                 }
             }
+        }
 
-            self.manager.wait_all().await;
+        self.control_rx = Some(control);
+
+        info!("Game loop stopped at tick {}", self.server.tick().await);
+
+        Ok(())
+    }
+
+    /// Runs one tick right away instead of waiting for its turn, building the system graph
+    /// if needed, and returns the tick reached.
+    ///
+    /// Meant for tests and tools, game time only moves with the server clock.
+    pub async fn tick_once(&mut self) -> Result<Tick, SystemGraphError> {
+        if self.system_graph.is_none() {
+            self.build_system_graph()?;
+        }
 
-            // TODO: tracemeter time spent on each system
-            {
-                // Send events
-                let mut events_queue = self.server.events_queue.lock().await;
+        self.live_tick().await;
 
-                for event in events_queue.drain(..) {
-                    self.manager.send(WorkerJob::Event(Arc::new(event))).await;
+        Ok(self.server.tick().await)
+    }
+
+    /// Runs `ticks` ticks back to back, see [`GameLoop::tick_once`].
+    pub async fn run_ticks(&mut self, ticks: u64) -> Result<Tick, SystemGraphError> {
+        for _ in 0..ticks {
+            self.tick_once().await?;
+        }
+
+        Ok(self.server.tick().await)
+    }
+
+    /// Handles the events still queued, saves if `save` is set and stops the workers,
+    /// waiting up to `timeout` for them.
+    pub async fn shutdown(&mut self, save: bool, timeout: Duration) {
+        if !self.server.events_queue.lock().await.is_empty() {
+            self.process_events().await;
+        }
+
+        if save {
+            match self.server.save().await {
+                Ok(()) => info!("Saved world at tick {}", self.server.tick().await),
+                Err(err) => error!("Failed to save world on shutdown: {}", err),
+            }
+        }
+
+        self.manager.shutdown(timeout).await;
+    }
+
+    fn ticker(&self) -> Interval {
+        let mut ticker = tokio::time::interval(self.tick_duration);
+        ticker.set_missed_tick_behavior(self.missed_ticks.into());
+
+        ticker
+    }
+
+    /// Runs a tick outside of a replay, saving afterwards if a save was requested.
+    async fn live_tick(&mut self) {
+        let start_process = Instant::now();
+
+        self.run_tick(None).await;
+
+        let elapsed = start_process.elapsed();
+
+        let slowest = self.record_tick_stats(elapsed).await;
+
+        if elapsed > self.tick_duration {
+            match slowest {
+                Some((key, slowest)) => warn!(
+                    "Tick took longer than expected: got {}ms of {}ms range, slowest was {} with {}ms",
+                    elapsed.as_millis(),
+                    self.tick_duration.as_millis(),
+                    key,
+                    slowest.as_millis()
+                ),
+                None => warn!(
+                    "Tick took longer than expected: got {}ms of {}ms range",
+                    elapsed.as_millis(),
+                    self.tick_duration.as_millis()
+                ),
+            }
+        }
+
+        self.last_tick = Instant::now();
+
+        if let Some(interval) = self.timing_summary {
+            if self.last_timing_summary.elapsed() >= interval {
+                self.last_timing_summary = Instant::now();
+                self.log_timing_summary().await;
+            }
+        }
+
+        if self.server.take_save_request() {
+            if let Err(err) = self.server.save().await {
+                error!("Failed to save world: {}", err);
+            }
+        }
+    }
+
+    /// Records the tick and the timings of its jobs, returns the slowest job since the previous tick.
+    async fn record_tick_stats(&mut self, elapsed: Duration) -> Option<(TimingKey, Duration)> {
+        let mut stats = self.server.stats.write().await;
+
+        self.manager.merge_timings(&mut stats.timings);
+
+        stats.tick_durations.record(elapsed);
+        stats.target_tps = self.tps as f64 * self.speed;
+
+        let (since, ticks) = &mut self.tps_sample;
+        *ticks += 1;
+
+        let sample = since.elapsed();
+
+        if sample >= Duration::from_secs(1) {
+            stats.tps = *ticks as f64 / sample.as_secs_f64();
+            *since = Instant::now();
+            *ticks = 0;
+        }
+
+        stats.timings.slowest_in_tick
+    }
+
+    async fn log_timing_summary(&self) {
+        let stats = self.server.stats.read().await;
+
+        let slowest = stats.timings.slowest();
+
+        if slowest.is_empty() {
+            return;
+        }
+
+        info!("Slowest systems and events at tick {}:", stats.tick);
+
+        for (key, timing) in slowest.into_iter().take(TIMING_SUMMARY_LEN) {
+            info!("  {} {}", key, timing);
+        }
+    }
+
+    /// Applies a control command, returns whether the tick schedule must restart.
+    async fn apply_control(&mut self, command: ControlCommand) -> bool {
+        match command {
+            ControlCommand::Pause => {
+                info!("Game loop paused at tick {}", self.server.tick().await);
+                self.set_paused(true).await;
+            }
+            ControlCommand::Resume => {
+                info!("Game loop resumed at tick {}", self.server.tick().await);
+                self.set_paused(false).await;
+            }
+            ControlCommand::Step(ticks) => {
+                self.set_paused(true).await;
+
+                for _ in 0..ticks {
+                    self.live_tick().await;
                 }
+
+                info!(
+                    "Stepped {} ticks to tick {}",
+                    ticks,
+                    self.server.tick().await
+                );
+            }
+            ControlCommand::SetTps(0) => {
+                warn!("Ignoring tps of 0, pause the game loop instead");
+                return false;
+            }
+            ControlCommand::SetTps(tps) => {
+                self.tps = tps;
+                self.update_tick_duration();
+            }
+            ControlCommand::SetSpeed(speed) if !speed.is_finite() || speed <= 0.0 => {
+                warn!(
+                    "Ignoring game loop speed of {}, pause the game loop instead",
+                    speed
+                );
+                return false;
+            }
+            ControlCommand::SetSpeed(speed) => {
+                self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+
+                if self.speed != speed {
+                    warn!(
+                        "Game loop speed of {} out of range, using {}",
+                        speed, self.speed
+                    );
+                }
+
+                self.update_tick_duration();
+            }
+            // Stops the loop in `start`.
+            ControlCommand::Shutdown => return false,
+        }
+
+        true
+    }
+
+    async fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.server.stats.write().await.paused = paused;
+    }
+
+    fn update_tick_duration(&mut self) {
+        self.tick_duration = Duration::from_secs(1)
+            .div_f64(self.tps as f64 * self.speed)
+            .max(MIN_TICK_DURATION);
+
+        info!(
+            "Game loop running at tps: {} speed: {}x, tick on: {}ms",
+            self.tps,
+            self.speed,
+            self.tick_duration.as_millis()
+        );
+    }
+
+    /// Accounts for the ticks missed before the tick scheduled at `scheduled` started.
+    ///
+    /// With [`MissedTicks::Burst`] late ticks still run, only how far behind the loop is
+    /// gets recorded. Otherwise the gap since the `previous` scheduled tick tells how many
+    /// ticks were skipped or delayed.
+    async fn account_missed_ticks(&self, scheduled: Instant, previous: Option<Instant>) {
+        let tick_nanos = self.tick_duration.as_nanos().max(1);
+
+        let behind = Instant::now()
+            .saturating_duration_since(scheduled)
+            .as_nanos()
+            / tick_nanos;
+
+        let missed = previous.map_or(0, |previous| {
+            let gap = scheduled.saturating_duration_since(previous).as_nanos() / tick_nanos;
+
+            gap.saturating_sub(1)
+        });
+
+        let mut stats = self.server.stats.write().await;
+
+        if self.missed_ticks == MissedTicks::Burst && behind > 0 && stats.ticks_behind == 0 {
+            warn!("{} ticks behind schedule, catching up", behind);
+        }
+
+        if missed > 0 {
+            match self.missed_ticks {
+                MissedTicks::Delay => warn!("Delayed the tick schedule by {} ticks", missed),
+                _ => warn!("Skipped {} ticks to get back on schedule", missed),
+            }
+        }
+
+        stats.ticks_behind = behind;
+        stats.missed_ticks += missed;
+    }
+
+    /// Runs every tick covered by `records` back to back, dispatching the logged events
+    /// in place of the ones emitted live, and returns the tick the loop stopped at.
+    ///
+    /// Saves and the event log are disabled while replaying.
+    pub async fn replay(&mut self, records: Vec<EventRecord>) -> Tick {
+        let mut by_tick: BTreeMap<Tick, Vec<EventRecord>> = BTreeMap::new();
+
+        for record in records {
+            by_tick.entry(record.tick).or_default().push(record);
+        }
+
+        let start_tick = self.server.tick().await;
+
+        let Some(last_tick) = by_tick.keys().next_back().copied() else {
+            return start_tick;
+        };
+
+        if let Err(err) = self.build_system_graph() {
+            error!("Failed to replay: {}", err);
+            return start_tick;
+        }
+
+        let event_log = self.server.event_log.lock().await.take();
+
+        let start = Instant::now();
+        let mut tick = start_tick;
+
+        while tick <= last_tick {
+            let records = by_tick.remove(&tick).unwrap_or_default();
+
+            self.run_tick(Some(records)).await;
+            self.server.take_save_request();
+
+            tick = self.server.tick().await;
+        }
+
+        *self.server.event_log.lock().await = event_log;
+
+        info!(
+            "Replayed ticks {} to {} in {}ms",
+            start_tick,
+            last_tick,
+            start.elapsed().as_millis()
+        );
+
+        tick
+    }
+
+    /// Runs a whole tick, with the logged events of that tick when replaying.
+    async fn run_tick(&mut self, replay: Option<Vec<EventRecord>>) {
+        self.manager.supervise().await;
+
+        self.server.fire_scheduled_events().await;
+
+        self.run_systems().await;
+
+        match replay {
+            None => self.process_events().await,
+            Some(records) => self.replay_events(records).await,
+        }
+
+        self.server.next_tick().await;
+    }
+
+    /// Builds the [`SystemGraph`] of the registered systems, done by the loop when it starts.
+    pub fn build_system_graph(&mut self) -> Result<(), SystemGraphError> {
+        let nodes = self
+            .systems
+            .iter()
+            .map(|system| SystemNode {
+                name: system.handler.name(),
+                order: system.order,
+                before: system.schedule.before.clone(),
+                after: system.schedule.after.clone(),
+                access: system.schedule.access,
+            })
+            .collect::<Vec<_>>();
+
+        self.system_graph = Some(SystemGraph::build(&nodes)?);
+
+        Ok(())
+    }
+
+    /// Runs the systems due this tick, each one as soon as its dependencies are done
+    /// and no running system conflicts with its data access.
+    async fn run_systems(&mut self) {
+        let Some(graph) = &self.system_graph else {
+            error!("Systems ran before the system graph was built");
+            return;
+        };
+
+        let tick = self.server.tick().await;
+        let now = self.server.now();
+
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut run = graph.start();
+        let mut running: Vec<usize> = Vec::new();
+        let mut completed = 0;
+
+        while completed < graph.len() {
+            let mut idx = 0;
+
+            while idx < run.ready.len() {
+                let node = run.ready[idx];
+
+                let access = graph.access(node);
+
+                if running
+                    .iter()
+                    .any(|&other| graph.access(other).conflicts(access))
+                {
+                    idx += 1;
+                    continue;
+                }
+
+                run.ready.swap_remove(idx);
+
+                let system = &mut self.systems[node];
+
+                if system.should_run(tick, &self.server, now).await {
+                    running.push(node);
+
+                    self.manager
+                        .send(WorkerJob::System(
+                            system.handler.clone(),
+                            node,
+                            done_tx.clone(),
+                        ))
+                        .await;
+                } else {
+                    completed += 1;
+                    run.complete(graph, node);
+                }
+            }
+
+            if running.is_empty() {
+                if run.ready.is_empty() {
+                    break;
+                }
+
+                continue;
+            }
+
+            let Some((node, succeeded)) = done_rx.recv().await else {
+                break;
+            };
+
+            self.systems[node].record_run(succeeded, self.failure_policy);
+
+            running.retain(|&other| other != node);
+            completed += 1;
+            run.complete(graph, node);
+        }
+    }
+
+    fn max_event_depth(&self) -> usize {
+        match self.event_cascade {
+            EventCascade::NextTick => 1,
+            EventCascade::SameTick(max_depth) => max_depth.max(1),
+        }
+    }
+
+    /// Runs the event phase of a tick, one sub-phase per cascade level.
+    async fn process_events(&mut self) {
+        let max_depth = self.max_event_depth();
+        let tick = self.server.tick().await;
+
+        let mut processed = 0;
+
+        for depth in 0..max_depth {
+            // Take the queue so handlers can push follow-up events while this phase runs.
+            let events = std::mem::take(&mut *self.server.events_queue.lock().await);
+
+            if events.is_empty() {
+                break;
+            }
+
+            processed += events.len();
+
+            self.log_events(&events, tick, depth).await;
+
+            for event in events {
+                self.dispatch(event).await;
             }
 
             // Wait for all events to be processed
             self.manager.wait_all().await;
+        }
+
+        {
+            let mut stats = self.server.stats.write().await;
+            stats.events_processed += processed as u64;
+            stats.tick_events = processed;
+        }
+
+        if let Some(event_log) = self.server.event_log.lock().await.as_mut() {
+            if let Err(err) = event_log.flush() {
+                error!("tick: {} failed to flush event log: {}", tick, err);
+            }
+        }
 
-            let elapsed = start_process.elapsed();
+        if let EventCascade::SameTick(_) = self.event_cascade {
+            let deferred = self.server.events_queue.lock().await.len();
 
-            if elapsed > self.tick_duration {
+            if deferred > 0 {
                 warn!(
-                    "Tick took longer than expected: got {}ms of {}ms range",
-                    elapsed.as_millis(),
-                    self.tick_duration.as_millis()
+                    "Event cascade reached max depth of {}, deferring {} events to the next tick",
+                    max_depth, deferred
                 );
             }
+        }
+    }
+
+    /// Event phase of a replayed tick, live events are only checked against the logged ones.
+    async fn replay_events(&mut self, records: Vec<EventRecord>) {
+        let max_depth = self.max_event_depth();
+        let tick = self.server.tick().await;
+
+        let mut by_depth: BTreeMap<usize, Vec<EncodedEvent>> = BTreeMap::new();
+
+        for record in records {
+            by_depth.entry(record.depth).or_default().push(record.event);
+        }
+
+        for depth in 0..max_depth {
+            let live = std::mem::take(&mut *self.server.events_queue.lock().await);
+            let logged = by_depth.remove(&depth).unwrap_or_default();
+
+            if live.is_empty() && logged.is_empty() {
+                break;
+            }
+
+            // Handlers run in parallel, so only the events emitted are compared, not their order.
+            let mut live_names = live.iter().map(|e| e.get_name()).collect::<Vec<_>>();
+            let mut logged_names = logged.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+
+            live_names.sort_unstable();
+            logged_names.sort_unstable();
+
+            if live_names != logged_names {
+                warn!(
+                    "tick: {} replay diverged at depth {}: {} live events, {} logged events",
+                    tick,
+                    depth,
+                    live_names.len(),
+                    logged_names.len()
+                );
+            }
+
+            let events = {
+                let codecs = self.server.event_codecs.read().unwrap();
+
+                logged
+                    .iter()
+                    .filter_map(|encoded| match codecs.decode(encoded) {
+                        Some(Ok(event)) => Some(event),
+                        Some(Err(err)) => {
+                            error!(
+                                "tick: {} failed to decode logged event {}: {}",
+                                tick, encoded.name, err
+                            );
+                            None
+                        }
+                        None => {
+                            debug!("No handlers registered for event: {}", encoded.name);
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            for event in events {
+                self.dispatch(event).await;
+            }
 
-            self.last_tick = Instant::now();
-            self.server.next_tick().await;
+            self.manager.wait_all().await;
+        }
+    }
+
+    async fn log_events(&self, events: &[Box<dyn Event>], tick: Tick, depth: usize) {
+        let mut event_log = self.server.event_log.lock().await;
+
+        let Some(event_log) = event_log.as_mut() else {
+            return;
+        };
+
+        for event in events {
+            let record = match event.encode() {
+                Ok(event) => EventRecord { tick, depth, event },
+                Err(err) => {
+                    error!(
+                        "tick: {} failed to encode event {} for the event log: {}",
+                        tick,
+                        event.get_name(),
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            if let Err(err) = event_log.append(&record) {
+                error!("tick: {} failed to append to event log: {}", tick, err);
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, event: Box<dyn Event>) {
+        let handlers = self.events.handlers(event.as_ref());
+
+        if handlers.is_empty() {
+            debug!("No handlers registered for event: {}", event.get_name());
+            return;
+        }
+
+        let event = Arc::new(event);
+
+        for handler in handlers {
+            self.manager
+                .send(WorkerJob::Event(event.clone(), handler.clone()))
+                .await;
         }
     }
 
     pub fn add_system(&mut self, order: SOrder, system: impl TickHandler + 'static) {
-        let systems = self.systems.entry(order).or_insert_with(Vec::new);
-        systems.push(Arc::new(Box::new(system)));
+        self.add_system_with(order, SystemSchedule::default(), system);
+    }
+
+    /// Registers a system that only runs on ticks that are a multiple of `every`.
+    pub fn add_system_every(
+        &mut self,
+        order: SOrder,
+        every: Tick,
+        system: impl TickHandler + 'static,
+    ) {
+        self.add_system_with(order, SystemSchedule::every(every), system);
+    }
+
+    /// Registers a system that only runs when its `schedule` says so.
+    pub fn add_system_with(
+        &mut self,
+        order: SOrder,
+        schedule: SystemSchedule,
+        system: impl TickHandler + 'static,
+    ) {
+        self.system_graph = None;
+
+        self.systems.push(GameSystem {
+            handler: Arc::new(Box::new(system)),
+            order,
+            schedule,
+            last_run: None,
+            failures: 0,
+            disabled: false,
+        });
+    }
+
+    /// Registers a handler for every event of type `E`, multiple handlers per type are allowed.
+    pub fn add_event_handler<E, H>(&mut self, handler: H)
+    where
+        E: Event + DeserializeOwned + 'static,
+        H: EventHandler<E> + 'static,
+    {
+        self.server.register_event::<E>();
+        self.events.add_handler::<E, H>(handler);
     }
 }
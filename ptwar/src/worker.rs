@@ -1,130 +1,285 @@
-use crate::event::Event;
+use crate::event::{AnyEventHandler, Event};
+use crate::metrics::{TimingKey, TimingStats};
 use crate::system::{PtWarServer, Tick};
 use async_trait::async_trait;
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
-use log::warn;
+use futures::FutureExt;
+use log::{error, warn};
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Mutex, Notify, RwLock};
 use tokio::task::JoinHandle;
 
 #[async_trait]
 pub trait TickHandler: Send + Sync {
-    async fn handle(&self, _tick: Tick, _server: Arc<PtWarServer>) {
-        unimplemented!();
+    async fn handle(&self, tick: Tick, server: Arc<PtWarServer>);
+
+    /// Name of the system in logs and metrics.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
     }
 }
 
 #[derive(Clone)]
 pub enum WorkerJob {
     Tick(Arc<Box<dyn TickHandler>>),
-    Event(Arc<Box<dyn Event>>),
+    /// A system of the system graph, its index and whether it succeeded are sent back once it is done.
+    System(
+        Arc<Box<dyn TickHandler>>,
+        usize,
+        UnboundedSender<(usize, bool)>,
+    ),
+    Event(Arc<Box<dyn Event>>, Arc<dyn AnyEventHandler>),
+}
+
+/// How long a job took, or why it panicked, sent by the workers and merged into the
+/// [`TimingStats`] once per tick so jobs never wait on the stats lock.
+pub struct JobTiming {
+    key: TimingKey,
+    tick: Tick,
+    outcome: Result<Duration, String>,
+}
+
+/// Number of jobs sent to workers and not finished yet, waking waiters when it drops to zero.
+#[derive(Debug, Default)]
+pub struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    pub fn get(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    pub fn add(&self, jobs: usize) {
+        self.count.fetch_add(jobs, Ordering::AcqRel);
+    }
+
+    pub fn done(&self) {
+        if self.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+
+    /// Returns once no job is in flight.
+    pub async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+
+            // Registers the waiter before checking, so a job finishing in between still wakes us.
+            idle.as_mut().enable();
+
+            if self.get() == 0 {
+                return;
+            }
+
+            idle.await;
+        }
+    }
 }
 
+/// Dispatches jobs to a pool of workers through one shared queue, so whichever worker is
+/// free picks up the next job and a slow job only holds up the worker running it.
 pub struct PWorkerManager {
-    workers_tx: Vec<Sender<WorkerJob>>,
-    idx: usize,
+    server: Arc<PtWarServer>,
+    jobs_tx: Option<Sender<WorkerJob>>,
+    jobs_rx: Arc<Mutex<Receiver<WorkerJob>>>,
     workers_count: usize,
-    buffer_size: usize,
     workers: Vec<PWorker>,
     // Stats
-    messages_in_flight: Arc<Mutex<usize>>,
+    messages_in_flight: Arc<InFlight>,
+    timings_tx: UnboundedSender<JobTiming>,
+    timings_rx: UnboundedReceiver<JobTiming>,
 }
 
 impl PWorkerManager {
+    /// `buffer_size` is the number of jobs that can wait in the queue before `send` blocks.
     pub fn new(state: Arc<PtWarServer>, workers_count: usize, buffer_size: usize) -> Self {
-        let messages_in_flight = Arc::new(Mutex::new(0usize));
-
-        let mut workers_tx = Vec::with_capacity(workers_count);
-        let mut workers = Vec::with_capacity(workers_count);
-
-        for id in 0..workers_count {
-            let (tx, rx) = tokio::sync::mpsc::channel(buffer_size);
-
-            workers_tx.push(tx);
-
-            workers.push(PWorker::new(
-                id as u8,
-                state.clone(),
-                rx,
-                messages_in_flight.clone(),
-            ))
-        }
+        let messages_in_flight = Arc::new(InFlight::default());
+
+        let (jobs_tx, jobs_rx) = tokio::sync::mpsc::channel(buffer_size);
+        let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+        let (timings_tx, timings_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let workers = (0..workers_count)
+            .map(|id| {
+                PWorker::new(
+                    id as u8,
+                    state.clone(),
+                    jobs_rx.clone(),
+                    messages_in_flight.clone(),
+                    timings_tx.clone(),
+                )
+            })
+            .collect();
 
         PWorkerManager {
-            workers_tx,
-            idx: 0,
+            server: state,
+            jobs_tx: Some(jobs_tx),
+            jobs_rx,
             workers_count,
-            buffer_size,
             workers,
             messages_in_flight,
+            timings_tx,
+            timings_rx,
         }
     }
 
+    pub fn workers_count(&self) -> usize {
+        self.workers_count
+    }
+
     pub async fn send(&mut self, handler: WorkerJob) {
-        let idx = self.idx;
-        self.idx = (self.idx + 1) % self.workers_count;
+        self.messages_in_flight.add(1);
+        self.enqueue(handler).await;
+    }
+
+    pub async fn send_batch(&mut self, handlers: &[WorkerJob]) {
+        self.messages_in_flight.add(handlers.len());
 
-        {
-            let mut in_flight = self.messages_in_flight.lock().await;
-            *in_flight += 1;
+        for handler in handlers {
+            self.enqueue(handler.clone()).await;
         }
+    }
 
-        self.workers_tx[idx].send(handler).await.unwrap();
+    async fn enqueue(&self, handler: WorkerJob) {
+        let sent = match &self.jobs_tx {
+            Some(jobs_tx) => jobs_tx.send(handler).await.is_ok(),
+            None => false,
+        };
+
+        if !sent {
+            error!("Workers are stopped, dropping job");
+            self.messages_in_flight.done();
+        }
     }
 
-    pub async fn send_batch(&mut self, handlers: &Vec<WorkerJob>) {
-        {
-            let mut in_flight = self.messages_in_flight.lock().await;
-            *in_flight += handlers.len();
+    pub fn probe(&self) -> WorkerProbe {
+        WorkerProbe {
+            messages_in_flight: self.messages_in_flight.clone(),
+            statuses: self
+                .workers
+                .iter()
+                .map(|worker| worker.status.clone())
+                .collect(),
         }
+    }
 
-        let worker_count = self.workers_count;
+    /// Respawns the workers whose task ended, jobs run under `catch_unwind` so this only
+    /// happens on a bug in the worker itself.
+    pub async fn supervise(&mut self) {
+        if self.jobs_tx.is_none() {
+            return;
+        }
 
-        let batches = handlers.chunks(worker_count);
+        for worker in &mut self.workers {
+            if !worker.thread.is_finished() {
+                continue;
+            }
+
+            match (&mut worker.thread).await {
+                Ok(()) => warn!("Worker {} stopped, respawning it", worker.id),
+                Err(err) => error!("Worker {} died: {}, respawning it", worker.id, err),
+            }
 
-        let mut futures = FuturesUnordered::new();
+            worker.respawn(
+                self.server.clone(),
+                self.jobs_rx.clone(),
+                self.messages_in_flight.clone(),
+                self.timings_tx.clone(),
+            );
+        }
+    }
 
-        for batch in batches {
-            for handle in batch {
-                self.idx = (self.idx + 1) % self.workers_count;
-                let idx = self.idx.clone();
+    /// Closes every worker channel and waits up to `timeout` for the workers to finish their queued jobs.
+    pub async fn shutdown(&mut self, timeout: Duration) {
+        self.jobs_tx = None;
 
-                let inner_handle = handle.clone();
-                let inner_tx = self.workers_tx[idx].clone();
+        let deadline = tokio::time::Instant::now() + timeout;
 
-                futures.push(async move {
-                    inner_tx.send(inner_handle).await.unwrap();
-                });
+        for mut worker in self.workers.drain(..) {
+            match tokio::time::timeout_at(deadline, &mut worker.thread).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => error!("Worker {} stopped with an error: {}", worker.id, err),
+                Err(_) => {
+                    warn!("Worker {} did not stop in time, aborting it", worker.id);
+                    worker.thread.abort();
+                }
             }
         }
-
-        self.idx += handlers.len();
-
-        while let Some(_) = futures.next().await {}
     }
 
-    pub async fn wait_all(&self) {
-        loop {
-            let in_flight = self.messages_in_flight.lock().await;
-            if *in_flight == 0 {
-                break;
+    /// Records the timings of the jobs finished since the previous call into `stats`,
+    /// [`TimingStats::slowest_in_tick`] is the slowest of them.
+    pub fn merge_timings(&mut self, stats: &mut TimingStats) {
+        stats.slowest_in_tick = None;
+
+        while let Ok(timing) = self.timings_rx.try_recv() {
+            match timing.outcome {
+                Ok(elapsed) => stats.record(timing.key, elapsed),
+                Err(panic) => {
+                    let failures = stats.record_failure(timing.key);
+
+                    error!(
+                        "{} panicked at tick {} ({} failures so far): {}",
+                        timing.key, timing.tick, failures, panic
+                    );
+                }
             }
-
-            drop(in_flight);
-
-            tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
         }
     }
+
+    /// Returns as soon as every job sent so far is done.
+    pub async fn wait_all(&self) {
+        self.messages_in_flight.wait_idle().await;
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PWorkerStatus {
     Idle,
     Working,
     Stopped,
 }
 
+impl PWorkerStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PWorkerStatus::Idle => "idle",
+            PWorkerStatus::Working => "working",
+            PWorkerStatus::Stopped => "stopped",
+        }
+    }
+}
+
+/// Shared view of the workers of a [`PWorkerManager`], for metrics.
+#[derive(Clone)]
+pub struct WorkerProbe {
+    messages_in_flight: Arc<InFlight>,
+    statuses: Vec<Arc<RwLock<PWorkerStatus>>>,
+}
+
+impl WorkerProbe {
+    pub fn messages_in_flight(&self) -> usize {
+        self.messages_in_flight.get()
+    }
+
+    pub async fn statuses(&self) -> Vec<PWorkerStatus> {
+        let mut statuses = Vec::with_capacity(self.statuses.len());
+
+        for status in &self.statuses {
+            statuses.push(*status.read().await);
+        }
+
+        statuses
+    }
+}
+
 pub struct PWorker {
     id: u8,
     status: Arc<RwLock<PWorkerStatus>>,
@@ -140,50 +295,98 @@ impl PWorker {
     pub fn new(
         id: u8,
         server: Arc<PtWarServer>,
-        mut receiver: Receiver<WorkerJob>,
-        in_flight_count: Arc<Mutex<usize>>,
+        jobs: Arc<Mutex<Receiver<WorkerJob>>>,
+        in_flight_count: Arc<InFlight>,
+        timings: UnboundedSender<JobTiming>,
     ) -> Self {
         let status = Arc::new(RwLock::new(PWorkerStatus::Idle));
-        let status_cp = status.clone();
+        let thread = Self::spawn(status.clone(), server, jobs, in_flight_count, timings);
+
+        PWorker { id, status, thread }
+    }
 
-        let thread = tokio::spawn(async move {
+    fn respawn(
+        &mut self,
+        server: Arc<PtWarServer>,
+        jobs: Arc<Mutex<Receiver<WorkerJob>>>,
+        in_flight_count: Arc<InFlight>,
+        timings: UnboundedSender<JobTiming>,
+    ) {
+        self.thread = Self::spawn(self.status.clone(), server, jobs, in_flight_count, timings);
+    }
+
+    fn spawn(
+        status: Arc<RwLock<PWorkerStatus>>,
+        server: Arc<PtWarServer>,
+        jobs: Arc<Mutex<Receiver<WorkerJob>>>,
+        in_flight_count: Arc<InFlight>,
+        timings: UnboundedSender<JobTiming>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
             loop {
                 update_status(status.clone(), PWorkerStatus::Idle).await;
 
-                let msg = receiver.recv().await;
+                // Only the worker holding the lock waits on the queue, the others wait for the lock.
+                let msg = jobs.lock().await.recv().await;
 
                 update_status(status.clone(), PWorkerStatus::Working).await;
 
-                match msg {
-                    Some(msg) => {
-                        let tick = server.tick().await;
-
-                        match msg {
-                            WorkerJob::Tick(act) => act.handle(tick, server.clone()).await,
-                            WorkerJob::Event(act) => {
-                                warn!(
-                                    "tick: {} Event handling not implemented for event: {}",
-                                    tick,
-                                    act.get_name()
-                                );
-                            }
-                        };
-
-                        let mut in_flight = in_flight_count.lock().await;
-                        *in_flight -= 1;
-                    }
-                    None => {
-                        update_status(status.clone(), PWorkerStatus::Stopped).await;
-                        break;
+                let Some(msg) = msg else {
+                    update_status(status.clone(), PWorkerStatus::Stopped).await;
+                    break;
+                };
+
+                let tick = server.tick().await;
+                let started = Instant::now();
+
+                let (key, done) = match &msg {
+                    WorkerJob::Tick(act) => (TimingKey::System(act.name()), None),
+                    WorkerJob::System(act, node, done) => {
+                        (TimingKey::System(act.name()), Some((*node, done.clone())))
                     }
+                    WorkerJob::Event(event, _) => (TimingKey::Event(event.get_name()), None),
+                };
+
+                // A panicking job must not take the worker down nor leave the job in flight.
+                let result = AssertUnwindSafe(run_job(msg, tick, server.clone()))
+                    .catch_unwind()
+                    .await;
+
+                let outcome = match &result {
+                    Ok(()) => Ok(started.elapsed()),
+                    Err(panic) => Err(panic_message(panic.as_ref()).to_string()),
+                };
+
+                // Only fails once the manager is dropped, nobody is left to read the timing.
+                let _ = timings.send(JobTiming { key, tick, outcome });
+
+                if let Some((node, done)) = done {
+                    let _ = done.send((node, result.is_ok()));
                 }
+
+                in_flight_count.done();
             }
-        });
+        })
+    }
+}
 
-        PWorker {
-            id,
-            status: status_cp,
-            thread,
+async fn run_job(job: WorkerJob, tick: Tick, server: Arc<PtWarServer>) {
+    match job {
+        WorkerJob::Tick(act) | WorkerJob::System(act, _, _) => act.handle(tick, server).await,
+        WorkerJob::Event(event, handler) => {
+            handler
+                .handle_any(event.as_ref().as_ref(), tick, server)
+                .await
         }
     }
 }
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -1,44 +1,233 @@
 pub mod region;
 mod region_noise;
+pub mod snapshot;
+pub mod state_hash;
 pub mod tile;
 
+use crate::game::resource::ResourceStorage;
 use crate::system::Tick;
 use crate::world::region::{Region, RegionNoise};
+use crate::world::state_hash::{StateHash, StateHasher};
+use crate::world::tile::Tile;
+use hexx::storage::HexStore;
 use hexx::{shapes, Hex, HexLayout, HexOrientation, Vec2};
-use log::info;
+use log::{debug, info};
 use noise::{Fbm, NoiseFn, Perlin};
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
 use std::time::Instant;
 
+/// What changed in a [`PtWorld`] since it was last saved.
+#[derive(Default)]
+pub struct DirtyState {
+    /// Tick of the full snapshot the changes are relative to.
+    pub base: Option<Tick>,
+    /// Regions that must be written as a whole.
+    pub regions: HashSet<Hex>,
+    /// Tiles changed per region hex.
+    pub tiles: HashMap<Hex, HashSet<Hex>>,
+    /// Regions generated for the first time, see [`PtWorld::generated`].
+    pub generated: HashSet<Hex>,
+}
+
+impl DirtyState {
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty() && self.tiles.is_empty() && self.generated.is_empty()
+    }
+}
+
 pub struct PtWorld {
     pub last_save: Option<(Tick, Instant)>,
+    /// Regions currently in memory, see [`PtWorld::region_hexes`] for the whole world.
+    ///
+    /// Mutate regions through [`PtWorld::region_mut`] or [`PtWorld::tile_mut`],
+    /// direct changes are not tracked by incremental saves and are lost on eviction.
     pub regions: HashMap<Hex, Region>,
+    /// Every region of the world, loaded or not.
+    pub region_hexes: HashSet<Hex>,
+    /// Regions changed since they were generated, they are never evicted.
+    pub modified: HashSet<Hex>,
+    /// State hash of every region generated so far, as it was generated.
+    ///
+    /// Kept after the region is evicted, so [`PtWorld::state_hash`] covers generated content
+    /// without keeping every region loaded.
+    pub generated: HashMap<Hex, u64>,
+    pub seed: u32,
+    pub region_radius: u32,
+    pub dirty: DirtyState,
+    /// Regions accessed since the last [`PtWorld::evict_idle`].
+    accessed: Mutex<HashSet<Hex>>,
+}
+
+pub const DEFAULT_REGION_RADIUS: u32 = 100;
+pub const DEFAULT_WORLD_SIZE: u32 = 1;
+
+/// Layout of the regions of a new world, in region coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorldShape {
+    /// Hexagon of regions around the center one, `0` is a single region.
+    Hexagon(u32),
+    /// Rows of regions roughly centered on the origin.
+    Rectangle { width: u32, height: u32 },
+    /// Explicit region hexes, duplicates are ignored.
+    Custom(Vec<Hex>),
+}
+
+impl WorldShape {
+    /// Region hexes of the shape without duplicates, in generation order.
+    pub fn regions(&self) -> Vec<Hex> {
+        let hexes: Vec<Hex> = match self {
+            WorldShape::Hexagon(radius) => shapes::hexagon(Hex::ZERO, *radius).collect(),
+            WorldShape::Rectangle { width, height } => {
+                if *width == 0 || *height == 0 {
+                    return Vec::new();
+                }
+
+                let left = -(*width as i32 - 1) / 2;
+                let top = -(*height as i32 - 1) / 2;
+
+                shapes::pointy_rectangle([
+                    left,
+                    left + *width as i32 - 1,
+                    top,
+                    top + *height as i32 - 1,
+                ])
+                .collect()
+            }
+            WorldShape::Custom(hexes) => hexes.clone(),
+        };
+
+        let mut seen = HashSet::with_capacity(hexes.len());
+
+        hexes.into_iter().filter(|hex| seen.insert(*hex)).collect()
+    }
+}
+
+impl Default for WorldShape {
+    fn default() -> Self {
+        WorldShape::Hexagon(DEFAULT_WORLD_SIZE)
+    }
+}
+
+/// Parameters of a new world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorldConfig {
     pub seed: u32,
+    pub shape: WorldShape,
+    /// Radius in tiles of every region.
     pub region_radius: u32,
+    /// Generates regions on first access instead of all of them on start.
+    pub lazy: bool,
 }
 
-const DEFAULT_REGION_RADIUS: u32 = 100;
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            shape: WorldShape::default(),
+            region_radius: DEFAULT_REGION_RADIUS,
+            lazy: false,
+        }
+    }
+}
 
 impl PtWorld {
     pub fn from_seed(seed: u32) -> Self {
-        let region_radius = DEFAULT_REGION_RADIUS;
+        Self::generate(&WorldConfig {
+            seed,
+            ..Default::default()
+        })
+    }
+
+    /// World of `region_hexes` with no region loaded yet, they are generated on first access.
+    pub fn new(seed: u32, region_radius: u32, region_hexes: HashSet<Hex>) -> Self {
+        Self {
+            last_save: None,
+            regions: HashMap::new(),
+            region_hexes,
+            modified: HashSet::new(),
+            generated: HashMap::new(),
+            seed,
+            region_radius,
+            dirty: Default::default(),
+            accessed: Default::default(),
+        }
+    }
+
+    pub fn generate(config: &WorldConfig) -> Self {
+        let hexes = config.shape.regions();
+        let mut world = Self::new(
+            config.seed,
+            config.region_radius,
+            hexes.iter().copied().collect(),
+        );
+
+        if config.lazy {
+            info!(
+                "World of {} regions of radius {} from seed {}, generating regions on first access",
+                hexes.len(),
+                config.region_radius,
+                config.seed
+            );
+        } else {
+            world.load_regions(hexes);
+        }
+
+        world
+    }
+
+    /// Generates the regions among `hexes` that are not loaded yet, in parallel.
+    pub fn load_regions(&mut self, hexes: impl IntoIterator<Item = Hex>) {
+        let seed = self.seed;
+        let region_radius = self.region_radius;
+
+        let hexes = hexes
+            .into_iter()
+            .filter(|hex| self.region_hexes.contains(hex) && !self.regions.contains_key(hex))
+            .collect::<HashSet<Hex>>()
+            .into_iter()
+            .collect::<Vec<Hex>>();
+
+        if hexes.is_empty() {
+            return;
+        }
 
         let start = Instant::now();
+        let total = hexes.len();
+        let generated = AtomicUsize::new(0);
+        // Log about every tenth of the world, big worlds take minutes to generate.
+        let log_every = total.div_ceil(10).max(1);
+
+        info!(
+            "Generating {} regions of radius {} from seed {}",
+            total, region_radius, seed
+        );
 
-        let regions: HashMap<Hex, Region> = shapes::hexagon(Hex::ZERO, 1)
-            .collect::<Vec<Hex>>()
+        let regions: Vec<(Hex, Region, u64)> = hexes
             .par_iter()
             .map(|hex| {
                 let region = Region::new_with_noise(region_radius, RegionNoise { seed, hex: *hex });
+                let hash = region.state_hash();
+                let done = generated.fetch_add(1, Ordering::Relaxed) + 1;
 
-                (*hex, region)
+                if done.is_multiple_of(log_every) && done < total {
+                    info!(
+                        "Generated {}/{} regions in {}ms",
+                        done,
+                        total,
+                        start.elapsed().as_millis()
+                    );
+                }
+
+                (*hex, region, hash)
             })
             .collect();
 
         let tiles_count = regions
             .par_iter()
-            .map(|region| region.1.tiles.len())
+            .map(|(_, region, _)| region.tiles.len())
             .sum::<usize>();
 
         info!(
@@ -46,14 +235,229 @@ impl PtWorld {
             start.elapsed().as_millis(),
             tiles_count,
             regions.len(),
-            tiles_count / regions.len()
+            tiles_count / regions.len().max(1)
         );
 
-        Self {
-            last_save: None,
-            regions,
-            seed,
-            region_radius,
+        let accessed = self
+            .accessed
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        for (hex, region, hash) in regions {
+            accessed.insert(hex);
+
+            if self.generated.insert(hex, hash).is_none() {
+                self.dirty.generated.insert(hex);
+            }
+
+            self.regions.insert(hex, region);
+        }
+    }
+
+    /// Loaded region at `hex`, `None` when it is not loaded or not part of the world.
+    pub fn region(&self, hex: Hex) -> Option<&Region> {
+        let region = self.regions.get(&hex)?;
+
+        self.touch(hex);
+
+        Some(region)
+    }
+
+    /// Region at `hex`, generating it first when it is not loaded.
+    ///
+    /// Generating a region takes a while, prefer [`PtWorld::load_regions`] for many of them.
+    pub fn load_region(&mut self, hex: Hex) -> Option<&Region> {
+        self.ensure_loaded(hex);
+
+        self.region(hex)
+    }
+
+    fn ensure_loaded(&mut self, hex: Hex) -> bool {
+        if self.regions.contains_key(&hex) {
+            return true;
+        }
+
+        if !self.region_hexes.contains(&hex) {
+            return false;
+        }
+
+        let start = Instant::now();
+        let region = Region::new_with_noise(
+            self.region_radius,
+            RegionNoise {
+                seed: self.seed,
+                hex,
+            },
+        );
+
+        debug!(
+            "Generated region {:?} in {}ms",
+            hex,
+            start.elapsed().as_millis()
+        );
+
+        if self.generated.insert(hex, region.state_hash()).is_none() {
+            self.dirty.generated.insert(hex);
         }
+
+        self.regions.insert(hex, region);
+
+        true
+    }
+
+    fn touch(&self, hex: Hex) {
+        self.accessed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(hex);
+    }
+
+    /// Unloads the unmodified regions not accessed since the previous call, returning how many.
+    ///
+    /// They are generated again from the seed on their next access.
+    pub fn evict_idle(&mut self) -> usize {
+        let accessed = std::mem::take(
+            self.accessed
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        let before = self.regions.len();
+
+        self.regions
+            .retain(|hex, _| accessed.contains(hex) || self.modified.contains(hex));
+
+        before - self.regions.len()
+    }
+
+    /// Hash of every region generated so far, of its current content when it was modified,
+    /// sorted by region hex.
+    pub fn region_hashes(&self) -> Vec<(Hex, u64)> {
+        let mut hashes = self
+            .generated
+            .par_iter()
+            .map(|(hex, generated)| {
+                let hash = match self.regions.get(hex) {
+                    Some(region) if self.modified.contains(hex) => region.state_hash(),
+                    _ => *generated,
+                };
+
+                (*hex, hash)
+            })
+            .collect::<Vec<_>>();
+
+        hashes.sort_unstable_by_key(|(hex, _)| (hex.x, hex.y));
+
+        hashes
+    }
+
+    /// Stable hash of the whole world, two worlds with the same hash have the same state.
+    ///
+    /// Covers the content of every region generated so far, through [`PtWorld::generated`] for
+    /// the unmodified ones, so the hash does not depend on which regions are currently loaded.
+    /// Regions never generated only have their hex hashed, in a lazy world the hash changes
+    /// when one is generated for the first time.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+
+        hasher.write_u32(self.seed);
+        hasher.write_u32(self.region_radius);
+
+        let mut region_hexes = self.region_hexes.iter().collect::<Vec<_>>();
+        region_hexes.sort_unstable_by_key(|hex| (hex.x, hex.y));
+
+        for hex in region_hexes {
+            hasher.write_hex(*hex);
+        }
+
+        for (hex, hash) in self.region_hashes() {
+            hasher.write_hex(hex);
+            hasher.write_u64(hash);
+        }
+
+        hasher.finish()
+    }
+
+    /// Mutable access to a whole region, generated first when not loaded, marking it as changed.
+    pub fn region_mut(&mut self, region: Hex) -> Option<&mut Region> {
+        if !self.ensure_loaded(region) {
+            return None;
+        }
+
+        self.touch(region);
+        self.modified.insert(region);
+
+        let region_ref = self.regions.get_mut(&region)?;
+
+        self.dirty.regions.insert(region);
+        self.dirty.tiles.remove(&region);
+
+        Some(region_ref)
+    }
+
+    /// Mutable access to a single tile of a region, generated first when not loaded,
+    /// marking only that tile as changed.
+    pub fn tile_mut(&mut self, region: Hex, tile: Hex) -> Option<&mut (Tile, ResourceStorage)> {
+        if !self.ensure_loaded(region) {
+            return None;
+        }
+
+        self.touch(region);
+
+        let tile_ref = self.regions.get_mut(&region)?.tiles.get_mut(tile)?;
+
+        self.modified.insert(region);
+
+        if !self.dirty.regions.contains(&region) {
+            self.dirty.tiles.entry(region).or_default().insert(tile);
+        }
+
+        Some(tile_ref)
+    }
+
+    /// Forgets all tracked changes, they are now part of the save taken from `base`.
+    pub fn mark_saved(&mut self, base: Tick) {
+        self.dirty = DirtyState {
+            base: Some(base),
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eviction_keeps_state_hash() {
+        let mut world = PtWorld::generate(&WorldConfig {
+            seed: 42,
+            shape: WorldShape::Hexagon(1),
+            region_radius: 4,
+            lazy: true,
+        });
+        let lazy = world.state_hash();
+
+        world.load_regions(world.region_hexes.clone());
+        let hash = world.state_hash();
+        assert_ne!(hash, lazy, "generated content is not hashed");
+
+        world.evict_idle();
+        assert_eq!(world.evict_idle(), world.region_hexes.len());
+        assert!(world.load_region(Hex::ZERO).is_some());
+        assert_eq!(world.state_hash(), hash);
+
+        world
+            .tile_mut(Hex::ZERO, Hex::ZERO)
+            .unwrap()
+            .0
+            .infrastructure_level += 1;
+        let modified = world.state_hash();
+        assert_ne!(modified, hash);
+
+        world.evict_idle();
+        world.evict_idle();
+        assert!(world.regions.contains_key(&Hex::ZERO));
+        assert!(world.load_region(Hex::new(1, 0)).is_some());
+        assert_eq!(world.state_hash(), modified);
     }
 }
@@ -1,4 +1,5 @@
 use crate::game::resource::ResourceStorage;
+use crate::rng::region_rng;
 use crate::world::region_noise::NoiseGenerator;
 use crate::world::tile::{Biome, Tile};
 use hexx::algorithms::a_star;
@@ -7,13 +8,16 @@ use hexx::{Hex, HexBounds};
 use log::info;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
+#[derive(Serialize, Deserialize)]
 pub struct RegionNoise {
     pub(crate) seed: u32,
     pub(crate) hex: Hex,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Region {
     pub name: String,
     pub region_noise: RegionNoise,
@@ -29,8 +33,8 @@ pub struct RenderChunk {
 
 impl Region {
     // TODO: Move this to a utility module with proper tests and names.
-    pub fn random_name() -> String {
-        let s: String = rand::thread_rng()
+    pub fn random_name(rng: &mut impl Rng) -> String {
+        let s: String = rng
             .sample_iter(&Alphanumeric)
             .take(7)
             .map(char::from)
@@ -117,7 +121,7 @@ impl Region {
         }
 
         Self {
-            name: Self::random_name(),
+            name: Self::random_name(&mut region_rng(region_noise.seed, region_noise.hex)),
             tiles: hex_map,
             region_noise,
             cities,
@@ -1,5 +1,6 @@
 use crate::world::tile::Biome;
 use noise::*;
+use serde::{Deserialize, Serialize};
 use std::ops::{Div, Mul};
 const CONTINENT_FREQUENCY: f64 = 1.0;
 
@@ -27,7 +28,7 @@ pub fn height_noise_fn(seed: u32) -> impl NoiseFn<f64, 2> {
     Exponent::new(Abs::new(base)).set_exponent(1.4)
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MultiLayerNoiseValue {
     pub height: f64,
     pub temperature: f64,
@@ -0,0 +1,259 @@
+use crate::core::save::SAVE_MAGIC;
+use crate::game::resource::ResourceStorage;
+use crate::system::Tick;
+use crate::world::region::Region;
+use crate::world::tile::Tile;
+use crate::world::PtWorld;
+use hexx::storage::HexStore;
+use hexx::Hex;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Bumped every time the layout of [`WorldSnapshot`] changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct WorldSnapshotRef<'a> {
+    tick: Tick,
+    seed: u32,
+    region_radius: u32,
+    region_hexes: &'a HashSet<Hex>,
+    modified: &'a HashSet<Hex>,
+    generated: &'a HashMap<Hex, u64>,
+    regions: &'a HashMap<Hex, Region>,
+}
+
+/// Full copy of the loaded regions of a [`PtWorld`] at a given tick, as written to disk.
+///
+/// Regions missing from `regions` are generated from the seed when accessed.
+#[derive(Deserialize)]
+pub struct WorldSnapshot {
+    pub tick: Tick,
+    pub seed: u32,
+    pub region_radius: u32,
+    pub region_hexes: HashSet<Hex>,
+    pub modified: HashSet<Hex>,
+    pub generated: HashMap<Hex, u64>,
+    pub regions: HashMap<Hex, Region>,
+}
+
+#[derive(Serialize)]
+struct WorldDeltaRef<'a> {
+    base_tick: Tick,
+    tick: Tick,
+    regions: Vec<(Hex, &'a Region)>,
+    tiles: Vec<(Hex, Hex, &'a (Tile, ResourceStorage))>,
+    generated: Vec<(Hex, u64)>,
+}
+
+/// Regions and tiles changed between the full snapshot of `base_tick` and `tick`.
+///
+/// Deltas of the same base are incremental, each one only holds the changes since the
+/// previous save, so all of them must be applied in tick order.
+#[derive(Deserialize)]
+pub struct WorldDelta {
+    pub base_tick: Tick,
+    pub tick: Tick,
+    pub regions: Vec<(Hex, Region)>,
+    pub tiles: Vec<(Hex, Hex, (Tile, ResourceStorage))>,
+    /// Generated region hashes added since the previous save, see [`PtWorld::generated`].
+    pub generated: Vec<(Hex, u64)>,
+}
+
+pub(crate) fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+fn read_version(reader: &mut impl Read) -> io::Result<()> {
+    let version: u32 = bincode::deserialize_from(reader).map_err(invalid_data)?;
+
+    if version.to_le_bytes() == SAVE_MAGIC {
+        return Err(invalid_data(
+            "file is a save store save, read it with SaveStore::load_snapshot",
+        ));
+    }
+
+    if version != SNAPSHOT_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported save version {}, expected {}",
+            version, SNAPSHOT_VERSION
+        )));
+    }
+
+    Ok(())
+}
+
+impl PtWorld {
+    /// Writes a full snapshot of the world taken at `tick` to `path`.
+    pub fn save(&self, tick: Tick, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path.as_ref())?);
+
+        self.write_snapshot(tick, &mut writer)?;
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        Ok(())
+    }
+
+    /// Reads a snapshot written by [`PtWorld::save`], returning the world and the tick it was taken at.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<(Self, Tick)> {
+        Self::read_snapshot(&mut BufReader::new(File::open(path.as_ref())?))
+    }
+
+    pub fn write_snapshot(&self, tick: Tick, writer: &mut impl Write) -> io::Result<()> {
+        let start = Instant::now();
+
+        let snapshot = WorldSnapshotRef {
+            tick,
+            seed: self.seed,
+            region_radius: self.region_radius,
+            region_hexes: &self.region_hexes,
+            modified: &self.modified,
+            generated: &self.generated,
+            regions: &self.regions,
+        };
+
+        bincode::serialize_into(&mut *writer, &SNAPSHOT_VERSION).map_err(invalid_data)?;
+        bincode::serialize_into(&mut *writer, &snapshot).map_err(invalid_data)?;
+
+        info!(
+            "World saved at tick {} in {}ms",
+            tick,
+            start.elapsed().as_millis()
+        );
+
+        Ok(())
+    }
+
+    pub fn read_snapshot(reader: &mut impl Read) -> io::Result<(Self, Tick)> {
+        let start = Instant::now();
+
+        read_version(&mut *reader)?;
+
+        let snapshot: WorldSnapshot = bincode::deserialize_from(reader).map_err(invalid_data)?;
+
+        info!(
+            "World loaded at tick {} with {} of {} regions in {}ms",
+            snapshot.tick,
+            snapshot.regions.len(),
+            snapshot.region_hexes.len(),
+            start.elapsed().as_millis()
+        );
+
+        let mut world = Self::new(snapshot.seed, snapshot.region_radius, snapshot.region_hexes);
+
+        world.last_save = Some((snapshot.tick, Instant::now()));
+        world.regions = snapshot.regions;
+        world.modified = snapshot.modified;
+        world.generated = snapshot.generated;
+
+        world.mark_saved(snapshot.tick);
+
+        Ok((world, snapshot.tick))
+    }
+
+    /// Writes the changes tracked in [`PtWorld::dirty`] as a delta of the snapshot they are based on.
+    pub fn write_delta(&self, tick: Tick, writer: &mut impl Write) -> io::Result<()> {
+        let base_tick = self
+            .dirty
+            .base
+            .ok_or_else(|| invalid_data("world has no base snapshot to write a delta from"))?;
+
+        let regions = self
+            .dirty
+            .regions
+            .iter()
+            .filter_map(|hex| self.regions.get(hex).map(|region| (*hex, region)))
+            .collect::<Vec<_>>();
+
+        let tiles = self
+            .dirty
+            .tiles
+            .iter()
+            .filter_map(|(region_hex, tiles)| {
+                self.regions
+                    .get(region_hex)
+                    .map(|region| (region_hex, region, tiles))
+            })
+            .flat_map(|(region_hex, region, tiles)| {
+                tiles
+                    .iter()
+                    .filter_map(|hex| region.tiles.get(*hex).map(|tile| (*region_hex, *hex, tile)))
+            })
+            .collect::<Vec<_>>();
+
+        let generated = self
+            .dirty
+            .generated
+            .iter()
+            .filter_map(|hex| Some((*hex, *self.generated.get(hex)?)))
+            .collect::<Vec<_>>();
+
+        let delta = WorldDeltaRef {
+            base_tick,
+            tick,
+            regions,
+            tiles,
+            generated,
+        };
+
+        bincode::serialize_into(&mut *writer, &SNAPSHOT_VERSION).map_err(invalid_data)?;
+        bincode::serialize_into(&mut *writer, &delta).map_err(invalid_data)?;
+
+        info!(
+            "World delta saved at tick {} with {} regions and {} tiles",
+            tick,
+            delta.regions.len(),
+            delta.tiles.len(),
+        );
+
+        Ok(())
+    }
+
+    /// Replays a delta written by [`PtWorld::write_delta`] on top of this world, returning its tick.
+    pub fn read_delta(&mut self, reader: &mut impl Read) -> io::Result<Tick> {
+        read_version(&mut *reader)?;
+
+        let delta: WorldDelta = bincode::deserialize_from(reader).map_err(invalid_data)?;
+
+        if self.dirty.base != Some(delta.base_tick) {
+            return Err(invalid_data(format!(
+                "delta of tick {} is based on snapshot {}, world is based on {:?}",
+                delta.tick, delta.base_tick, self.dirty.base
+            )));
+        }
+
+        self.generated.extend(delta.generated);
+
+        for (hex, region) in delta.regions {
+            self.region_hexes.insert(hex);
+            self.modified.insert(hex);
+            self.regions.insert(hex, region);
+        }
+
+        // Regions first changed after the base snapshot are not in it, generate them.
+        self.load_regions(delta.tiles.iter().map(|(region_hex, _, _)| *region_hex));
+
+        for (region_hex, hex, value) in delta.tiles {
+            if let Some(tile) = self
+                .regions
+                .get_mut(&region_hex)
+                .and_then(|region| region.tiles.get_mut(hex))
+            {
+                self.modified.insert(region_hex);
+                *tile = value;
+            }
+        }
+
+        self.last_save = Some((delta.tick, Instant::now()));
+
+        Ok(delta.tick)
+    }
+}
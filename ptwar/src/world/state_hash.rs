@@ -0,0 +1,159 @@
+//! Stable hashes of the world state, equal across runs, machines and Rust releases.
+//!
+//! Two servers, or a run and its replay, are in sync when their hashes match. Golden values
+//! of a generated region catch unintended changes to world generation.
+
+use crate::world::region::Region;
+use crate::world::region_noise::MultiLayerNoiseValue;
+use crate::world::tile::{Biome, Tile};
+use hexx::storage::HexStore;
+use hexx::Hex;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a over the explicit bytes of the state, [`std::hash`] makes no stability guarantees.
+#[derive(Debug, Clone)]
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl StateHasher {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.write(&[value]);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    /// Hashes the exact bits, so `0.0` and `-0.0` differ.
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+
+    pub fn write_hex(&mut self, hex: Hex) {
+        self.write_i32(hex.x);
+        self.write_i32(hex.y);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Part of the world state that goes into [`PtWorld::state_hash`](crate::world::PtWorld::state_hash).
+///
+/// Implementations hash every field that affects gameplay in a fixed order, unordered
+/// collections must be sorted first.
+pub trait StateHash {
+    fn hash_state(&self, hasher: &mut StateHasher);
+
+    fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        self.hash_state(&mut hasher);
+
+        hasher.finish()
+    }
+}
+
+impl StateHash for Biome {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_u8(*self as u8);
+    }
+}
+
+impl StateHash for MultiLayerNoiseValue {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_f64(self.height);
+        hasher.write_f64(self.temperature);
+        hasher.write_f64(self.humidity);
+        hasher.write_f64(self.special);
+    }
+}
+
+impl StateHash for Tile {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_hex(self.hex);
+        self.biome.hash_state(hasher);
+        hasher.write_u8(self.infrastructure_level);
+        hasher.write_u8(self.wight);
+        hasher.write_u8(self.slots);
+        self.noise.hash_state(hasher);
+    }
+}
+
+impl StateHash for Region {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_str(&self.name);
+        hasher.write_u32(self.region_noise.seed);
+        hasher.write_hex(self.region_noise.hex);
+
+        hasher.write_u64(self.tiles.len() as u64);
+
+        for (_, (tile, storage)) in self.tiles.iter() {
+            tile.hash_state(hasher);
+            storage.hash_state(hasher);
+        }
+
+        hasher.write_u64(self.cities.len() as u64);
+
+        for city in &self.cities {
+            hasher.write_hex(city.center);
+            hasher.write_u32(city.radius);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{PtWorld, WorldConfig, WorldShape};
+
+    /// Update the values only for intended changes to world generation or to the hashes.
+    #[test]
+    fn golden_world() {
+        let world = PtWorld::generate(&WorldConfig {
+            seed: 42,
+            shape: WorldShape::Hexagon(1),
+            region_radius: 4,
+            lazy: false,
+        });
+
+        let mut hexes = world.regions.keys().copied().collect::<Vec<_>>();
+        hexes.sort_unstable_by_key(|hex| (hex.x, hex.y));
+
+        let mut hasher = StateHasher::default();
+
+        for hex in hexes {
+            hasher.write_hex(hex);
+            world.regions[&hex].hash_state(&mut hasher);
+        }
+
+        assert_eq!(hasher.finish(), 0xd77f_f06d_4c27_c417);
+        assert_eq!(world.state_hash(), 0x4a3d_f9ed_4139_8b96);
+    }
+}
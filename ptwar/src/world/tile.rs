@@ -1,8 +1,9 @@
 use crate::world::region_noise::MultiLayerNoiseValue;
 use hexx::{Hex, HexBounds};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Biome {
     Water,
     Desert,
@@ -47,6 +48,7 @@ impl Biome {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Tile {
     pub hex: Hex,
     pub biome: Biome,
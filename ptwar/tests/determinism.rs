@@ -0,0 +1,74 @@
+//! Runs the same seeded game twice tick by tick and compares the world state hashes,
+//! any gameplay randomness not drawn from the seeded streams makes the runs diverge.
+
+use async_trait::async_trait;
+use hexx::Hex;
+use ptwar::clock::ManualClock;
+use ptwar::system::{GameLoop, PtWarServer, SOrder, Tick};
+use ptwar::worker::TickHandler;
+use ptwar::world::{PtWorld, WorldConfig, WorldShape};
+use rand::Rng;
+use std::sync::Arc;
+
+const TICKS: u64 = 50;
+
+/// Raises the infrastructure of a few random tiles every tick.
+struct Build;
+
+#[async_trait]
+impl TickHandler for Build {
+    async fn handle(&self, _tick: Tick, server: Arc<PtWarServer>) {
+        let mut rng = server.rng("build").await;
+        let mut world = server.world.write().await;
+
+        let mut regions = world.region_hexes.iter().copied().collect::<Vec<_>>();
+        regions.sort_unstable_by_key(|hex| (hex.x, hex.y));
+
+        let radius = world.region_radius as i32;
+
+        for _ in 0..4 {
+            let region = regions[rng.gen_range(0..regions.len())];
+            let tile = Hex::new(
+                rng.gen_range(-radius..=radius),
+                rng.gen_range(-radius..=radius),
+            );
+
+            if let Some((tile, _)) = world.tile_mut(region, tile) {
+                tile.infrastructure_level = tile.infrastructure_level.wrapping_add(rng.gen());
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "build"
+    }
+}
+
+/// World state hash before and after running [`TICKS`] ticks of a new world of `seed`.
+async fn run(seed: u32) -> (u64, u64) {
+    let world = PtWorld::generate(&WorldConfig {
+        seed,
+        shape: WorldShape::Hexagon(1),
+        region_radius: 4,
+        lazy: false,
+    });
+    let before = world.state_hash();
+    let server = PtWarServer::from_world(world, 0).with_clock(Arc::new(ManualClock::new()));
+
+    let mut gloop = GameLoop::with_server(server, 2, 60);
+    gloop.add_system(SOrder::First, Build);
+    gloop.run_ticks(TICKS).await.unwrap();
+
+    let after = gloop.server().world.read().await.state_hash();
+
+    (before, after)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn same_seed_same_state() {
+    let (before, first) = run(7).await;
+    let (_, second) = run(7).await;
+
+    assert_ne!(before, first, "the ticks did not change the world");
+    assert_eq!(first, second);
+}
@@ -0,0 +1,109 @@
+//! Drives a game loop with a manual clock: a time based system emits an event
+//! whose handler cascades follow-up events.
+
+use async_trait::async_trait;
+use ptwar::clock::ManualClock;
+use ptwar::event::EventHandler;
+use ptwar::system::{EventCascade, GameLoop, PtWarServer, SOrder, SystemSchedule, Tick};
+use ptwar::worker::TickHandler;
+use ptwar::world::PtWorld;
+use ptwar_macros::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CASCADE_DEPTH: u8 = 2;
+
+/// Pings handled so far as `(tick, depth)`.
+type Pings = Arc<Mutex<Vec<(Tick, u8)>>>;
+
+#[derive(Event, Serialize, Deserialize)]
+struct Ping {
+    depth: u8,
+}
+
+/// Emits a [`Ping`] every time it runs.
+struct Heartbeat;
+
+#[async_trait]
+impl TickHandler for Heartbeat {
+    async fn handle(&self, _tick: Tick, server: Arc<PtWarServer>) {
+        server.add_event(Ping { depth: 0 }).await;
+    }
+
+    fn name(&self) -> &'static str {
+        "heartbeat"
+    }
+}
+
+/// Records every ping and answers with a deeper one.
+struct Echo(Pings);
+
+#[async_trait]
+impl EventHandler<Ping> for Echo {
+    async fn handle(&self, event: &Ping, tick: Tick, server: Arc<PtWarServer>) {
+        self.0.lock().unwrap().push((tick, event.depth));
+
+        if event.depth < CASCADE_DEPTH {
+            server
+                .add_event(Ping {
+                    depth: event.depth + 1,
+                })
+                .await;
+        }
+    }
+}
+
+fn game_loop(cascade: EventCascade) -> (GameLoop, Arc<ManualClock>, Pings) {
+    let clock = Arc::new(ManualClock::new());
+    let pings = Arc::new(Mutex::new(Vec::new()));
+
+    let server =
+        PtWarServer::from_world(PtWorld::new(0, 1, HashSet::new()), 0).with_clock(clock.clone());
+
+    let mut gloop = GameLoop::with_server(server, 2, 60);
+    gloop.set_event_cascade(cascade);
+    gloop.add_system_with(
+        SOrder::First,
+        SystemSchedule::every_duration(Duration::from_secs(1)),
+        Heartbeat,
+    );
+    gloop.add_event_handler::<Ping, _>(Echo(pings.clone()));
+
+    (gloop, clock, pings)
+}
+
+fn take(pings: &Pings) -> Vec<(Tick, u8)> {
+    std::mem::take(&mut *pings.lock().unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn time_cadence_follows_the_clock() {
+    let (mut gloop, clock, pings) = game_loop(EventCascade::SameTick(8));
+
+    // Runs right away, then not until a second of game time went by, however many ticks run.
+    assert_eq!(gloop.run_ticks(4).await.unwrap(), 4);
+    assert_eq!(take(&pings), [(0, 0), (0, 1), (0, 2)]);
+
+    clock.advance(Duration::from_millis(999));
+    gloop.run_ticks(2).await.unwrap();
+    assert!(take(&pings).is_empty());
+
+    clock.advance(Duration::from_millis(1));
+    gloop.run_ticks(2).await.unwrap();
+    assert_eq!(take(&pings), [(6, 0), (6, 1), (6, 2)]);
+
+    let server = gloop.server();
+    assert_eq!(server.tick().await, 8);
+    assert_eq!(server.stats.read().await.events_processed, 6);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn next_tick_cascade_defers_follow_ups() {
+    let (mut gloop, _clock, pings) = game_loop(EventCascade::NextTick);
+
+    gloop.run_ticks(4).await.unwrap();
+
+    assert_eq!(take(&pings), [(0, 0), (1, 1), (2, 2)]);
+}
@@ -0,0 +1,163 @@
+//! Writes saves to a temporary directory and reads them back through the [`SaveStore`].
+
+use hexx::Hex;
+use ptwar::core::event_log;
+use ptwar::core::save::SaveStore;
+use ptwar::event::EventScheduler;
+use ptwar::system::Tick;
+use ptwar::world::{PtWorld, WorldConfig, WorldShape};
+use std::fs;
+use std::path::PathBuf;
+
+/// Empty directory only used by the test `name`.
+fn save_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ptwar-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+}
+
+fn world(seed: u32) -> PtWorld {
+    PtWorld::generate(&WorldConfig {
+        seed,
+        shape: WorldShape::Hexagon(1),
+        region_radius: 3,
+        lazy: false,
+    })
+}
+
+fn build(world: &mut PtWorld, tick: Tick) {
+    if let Some((tile, _)) = world.tile_mut(Hex::ZERO, Hex::new(1, 0)) {
+        tile.infrastructure_level = tile.infrastructure_level.wrapping_add(tick as u8 + 1);
+    }
+}
+
+fn ticks(saves: Vec<(Tick, PathBuf)>) -> Vec<Tick> {
+    saves.into_iter().map(|(tick, _)| tick).collect()
+}
+
+#[test]
+fn write_load_and_rotate() {
+    let dir = save_dir("write-load-and-rotate");
+    let store = SaveStore::new(&dir, 2, 2);
+
+    let mut world = world(3);
+    let mut scheduled_events = EventScheduler::default();
+
+    for tick in (0..=60).step_by(10) {
+        build(&mut world, tick);
+
+        let path = store
+            .write(&mut world, &mut scheduled_events, tick)
+            .unwrap()
+            .expect("the world changed since the previous save");
+        assert!(path.exists());
+
+        let save = store.load_latest().unwrap().unwrap();
+        assert_eq!(save.tick, tick);
+        assert_eq!(save.world.state_hash(), world.state_hash());
+    }
+
+    // Snapshots at 0, 30 and 60 with two deltas in between, the deltas of 30 were compacted.
+    assert_eq!(ticks(store.snapshots().unwrap()), [30, 60]);
+    assert!(store.deltas().unwrap().is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn new_game_archives_previous_saves() {
+    let dir = save_dir("new-game-archives");
+    let store = SaveStore::new(&dir, 2, 0);
+
+    let mut old = world(3);
+    let mut scheduled_events = EventScheduler::default();
+
+    for tick in [100, 200] {
+        build(&mut old, tick);
+        store.write(&mut old, &mut scheduled_events, tick).unwrap();
+    }
+
+    fs::write(dir.join("events-200.log"), []).unwrap();
+
+    let archive = store.claim(None).unwrap().expect("old saves were archived");
+    assert!(store.snapshots().unwrap().is_empty());
+    assert!(event_log::segments(&dir).unwrap().is_empty());
+    assert!(archive.join("world-100.bin").exists());
+    assert!(archive.join("world-200.bin").exists());
+    assert!(archive.join("events-200.log").exists());
+
+    let mut new = world(4);
+    let path = store
+        .write(&mut new, &mut EventScheduler::default(), 0)
+        .unwrap()
+        .unwrap();
+
+    assert!(path.exists());
+    assert_eq!(store.load_latest().unwrap().unwrap().tick, 0);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn resume_keeps_own_saves() {
+    let dir = save_dir("resume-keeps-own");
+    let store = SaveStore::new(&dir, 0, 0);
+
+    let mut world = world(3);
+    let mut scheduled_events = EventScheduler::default();
+
+    for tick in [100, 200] {
+        build(&mut world, tick);
+        store
+            .write(&mut world, &mut scheduled_events, tick)
+            .unwrap();
+    }
+
+    // Events logged after the last save are logged again once resumed.
+    fs::write(dir.join("events-100.log"), []).unwrap();
+    fs::write(dir.join("events-200.log"), []).unwrap();
+
+    assert!(store.claim(Some(200)).unwrap().is_none());
+    assert_eq!(ticks(store.snapshots().unwrap()), [100, 200]);
+    assert_eq!(ticks(event_log::segments(&dir).unwrap()), [100]);
+
+    // Resuming from the older save branches off, the newer one is archived.
+    assert!(store.claim(Some(100)).unwrap().is_some());
+    assert!(store.snapshots().unwrap().is_empty());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn lazy_world_keeps_generated_hashes() {
+    let dir = save_dir("lazy-world");
+    let store = SaveStore::new(&dir, 0, 4);
+
+    let mut world = PtWorld::generate(&WorldConfig {
+        seed: 3,
+        shape: WorldShape::Hexagon(1),
+        region_radius: 3,
+        lazy: true,
+    });
+    let mut scheduled_events = EventScheduler::default();
+
+    store.write(&mut world, &mut scheduled_events, 0).unwrap();
+
+    // Only generated, the delta still carries its hash.
+    world.load_region(Hex::new(1, 0));
+    world.evict_idle();
+    world.evict_idle();
+    assert!(world.regions.is_empty());
+
+    store
+        .write(&mut world, &mut scheduled_events, 10)
+        .unwrap()
+        .expect("a region was generated since the previous save");
+
+    let save = store.load_latest().unwrap().unwrap();
+    assert_eq!(save.tick, 10);
+    assert_eq!(save.world.state_hash(), world.state_hash());
+
+    let _ = fs::remove_dir_all(&dir);
+}
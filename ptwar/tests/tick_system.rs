@@ -0,0 +1,55 @@
+//! Registers `#[tick_system]` functions and checks the loop follows their declared schedule.
+
+use ptwar::clock::ManualClock;
+use ptwar::system::{GameLoop, PtWarServer, Tick};
+use ptwar::world::PtWorld;
+use ptwar_macros::tick_system;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+static RUNS: Mutex<Vec<(&str, Tick)>> = Mutex::new(Vec::new());
+
+async fn after_warmup(tick: Tick, server: Arc<PtWarServer>) -> bool {
+    tick >= 4 && server.world.read().await.region_hexes.is_empty()
+}
+
+#[tick_system(order = Second, every = 2, name = "produce", writes = World)]
+async fn produce(tick: Tick, _server: Arc<PtWarServer>) {
+    RUNS.lock().unwrap().push(("produce", tick));
+}
+
+#[tick_system(
+    order = Second,
+    every = 2,
+    run_if = after_warmup,
+    after = "produce",
+    reads = World,
+    writes = Stats
+)]
+async fn consume(tick: Tick, _server: Arc<PtWarServer>) {
+    RUNS.lock().unwrap().push(("consume", tick));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn follows_declared_schedule() {
+    let server = PtWarServer::from_world(PtWorld::new(0, 1, HashSet::new()), 0)
+        .with_clock(Arc::new(ManualClock::new()));
+
+    let mut gloop = GameLoop::with_server(server, 2, 60);
+    consume::register(&mut gloop);
+    produce::register(&mut gloop);
+
+    gloop.run_ticks(8).await.unwrap();
+
+    assert_eq!(
+        *RUNS.lock().unwrap(),
+        [
+            ("produce", 0),
+            ("produce", 2),
+            ("produce", 4),
+            ("consume", 4),
+            ("produce", 6),
+            ("consume", 6),
+        ]
+    );
+}